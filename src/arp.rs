@@ -0,0 +1,298 @@
+use crate::rng::Rng;
+
+use std::fmt;
+
+//how many held notes an arp keeps track of
+pub const ARP_MAX:usize = 16;
+pub const OCTAVES_MAX:u8 = 4;
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum ArpPattern
+{
+	Up,
+	Down,
+	UpDown,
+	AsPlayed,
+	Random
+}
+
+const PATTERNS : [ArpPattern;5] =
+[
+	ArpPattern::Up,
+	ArpPattern::Down,
+	ArpPattern::UpDown,
+	ArpPattern::AsPlayed,
+	ArpPattern::Random
+];
+
+impl ArpPattern
+{
+	pub fn parse(txt : &str) -> Option<ArpPattern>
+	{
+		PATTERNS.iter().find(|p| p.to_string() == txt).copied()
+	}
+}
+
+impl fmt::Display for ArpPattern
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			ArpPattern::Up => write!(f,"up"),
+			ArpPattern::Down => write!(f,"down"),
+			ArpPattern::UpDown => write!(f,"updown"),
+			ArpPattern::AsPlayed => write!(f,"played"),
+			ArpPattern::Random => write!(f,"random")
+		}
+	}
+}
+
+//an arp slot plays the notes held in its key range instead of the notes on its steps,
+//the steps still decide when it plays, rests and ties included
+#[derive(Debug,Copy,Clone)]
+pub struct Arp
+{
+	pub pattern: ArpPattern,
+	pub octaves: u8,
+
+	notes: [u8;ARP_MAX], //in the order they were played
+	count: usize,
+	keys_down: usize,
+	index: usize
+}
+
+impl Arp
+{
+	pub fn new(pattern : ArpPattern) -> Arp
+	{
+		Arp
+		{
+			pattern,
+			octaves: 1,
+			notes: [0;ARP_MAX],
+			count: 0,
+			keys_down: 0,
+			index: 0
+		}
+	}
+
+	pub fn notes(&self) -> &[u8]
+	{
+		&self.notes[0..self.count]
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.count == 0
+	}
+
+	//no keys down right now, whatever is left is latched
+	pub fn is_idle(&self) -> bool
+	{
+		self.keys_down == 0
+	}
+
+	//a latched arp swaps in a new chord once every key has been let go
+	pub fn press(&mut self, nn : u8, latch : bool)
+	{
+		if latch && self.keys_down == 0
+		{
+			self.count = 0;
+		}
+
+		self.keys_down += 1;
+
+		if self.count < ARP_MAX && !self.notes().contains(&nn)
+		{
+			self.notes[self.count] = nn;
+			self.count += 1;
+		}
+	}
+
+	pub fn release(&mut self, nn : u8, latch : bool)
+	{
+		self.keys_down = self.keys_down.saturating_sub(1);
+
+		if latch
+		{
+			return
+		}
+
+		if let Some(idx) = self.notes().iter().position(|n| *n == nn)
+		{
+			self.notes.copy_within(idx + 1..self.count,idx);
+			self.count -= 1;
+		}
+	}
+
+	pub fn clear(&mut self)
+	{
+		self.count = 0;
+		self.keys_down = 0;
+		self.index = 0;
+	}
+
+	pub fn reset(&mut self)
+	{
+		self.index = 0;
+	}
+
+	pub fn next_pattern(&mut self)
+	{
+		let idx = PATTERNS.iter().position(|p| *p == self.pattern).unwrap_or(0);
+		self.pattern = PATTERNS[(idx + 1) % PATTERNS.len()];
+	}
+
+	pub fn up_octaves(&mut self)
+	{
+		self.octaves = std::cmp::min(self.octaves + 1,OCTAVES_MAX);
+	}
+
+	pub fn down_octaves(&mut self)
+	{
+		self.octaves = std::cmp::max(self.octaves - 1,1);
+	}
+
+	//the held notes spread over the octave range, in playing order
+	fn run(&self) -> ([u8;ARP_MAX * OCTAVES_MAX as usize],usize)
+	{
+		let mut base = [0u8;ARP_MAX];
+		base[0..self.count].copy_from_slice(self.notes());
+
+		if self.pattern != ArpPattern::AsPlayed
+		{
+			base[0..self.count].sort_unstable();
+		}
+
+		let mut run = [0u8;ARP_MAX * OCTAVES_MAX as usize];
+		let mut len = 0;
+
+		for octave in 0..self.octaves as usize
+		{
+			for nn in base[0..self.count].iter()
+			{
+				let shifted = *nn as usize + octave * 12;
+
+				if shifted <= 0x7F
+				{
+					run[len] = shifted as u8;
+					len += 1;
+				}
+			}
+		}
+
+		(run,len)
+	}
+
+	pub fn next_note(&mut self, rng : &mut Rng) -> Option<u8>
+	{
+		let (run,len) = self.run();
+
+		if len == 0
+		{
+			return None
+		}
+
+		//up-down turns around on the end notes without playing them twice
+		let cycle = match self.pattern
+		{
+			ArpPattern::UpDown if len > 1 => 2 * len - 2,
+			_ => len
+		};
+
+		let pos = self.index % cycle;
+		self.index = (self.index + 1) % cycle;
+
+		let nn = match self.pattern
+		{
+			ArpPattern::Up | ArpPattern::AsPlayed => run[pos],
+			ArpPattern::Down => run[len - 1 - pos],
+			ArpPattern::UpDown => if pos < len { run[pos] } else { run[cycle - pos] },
+			ArpPattern::Random => run[rng.below(len)]
+		};
+
+		Some(nn)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	//held in this order, not sorted
+	fn arp_of(pattern : ArpPattern, held : &[u8]) -> Arp
+	{
+		let mut arp = Arp::new(pattern);
+
+		for nn in held.iter()
+		{
+			arp.press(*nn,false);
+		}
+
+		arp
+	}
+
+	fn take(arp : &mut Arp, n : usize) -> Vec<u8>
+	{
+		let mut rng = Rng::new(0);
+		(0..n).map(|_| arp.next_note(&mut rng).unwrap()).collect()
+	}
+
+	#[test]
+	fn patterns_walk_the_held_notes_in_order()
+	{
+		let held = [64,60,67];
+
+		assert_eq!(take(&mut arp_of(ArpPattern::Up,&held),4),[60,64,67,60]);
+		assert_eq!(take(&mut arp_of(ArpPattern::Down,&held),4),[67,64,60,67]);
+		assert_eq!(take(&mut arp_of(ArpPattern::UpDown,&held),6),[60,64,67,64,60,64]);
+		assert_eq!(take(&mut arp_of(ArpPattern::AsPlayed,&held),4),[64,60,67,64]);
+	}
+
+	#[test]
+	fn octaves_stack_the_run_above_itself()
+	{
+		let mut arp = arp_of(ArpPattern::Up,&[60,64]);
+		arp.octaves = 2;
+
+		assert_eq!(take(&mut arp,5),[60,64,72,76,60]);
+	}
+
+	#[test]
+	fn octaves_past_the_top_note_are_left_out()
+	{
+		let mut arp = arp_of(ArpPattern::Up,&[120]);
+		arp.octaves = 3;
+
+		assert_eq!(take(&mut arp,2),[120,120]);
+	}
+
+	#[test]
+	fn a_latched_arp_keeps_its_notes_until_the_next_chord()
+	{
+		let mut arp = arp_of(ArpPattern::Up,&[]);
+		arp.press(60,true);
+		arp.press(64,true);
+		arp.release(60,true);
+		arp.release(64,true);
+
+		assert_eq!(arp.notes(),[60,64]);
+		assert!(arp.is_idle());
+
+		arp.press(67,true);
+		assert_eq!(arp.notes(),[67]);
+	}
+
+	#[test]
+	fn letting_go_takes_the_note_out_unlatched()
+	{
+		let mut arp = arp_of(ArpPattern::AsPlayed,&[60,64,67]);
+		arp.release(64,false);
+
+		assert_eq!(arp.notes(),[60,67]);
+		assert_eq!(arp.next_note(&mut Rng::new(0)),Some(60));
+		assert_eq!(Arp::new(ArpPattern::Up).next_note(&mut Rng::new(0)),None);
+	}
+}
@@ -0,0 +1,12 @@
+//midi clock runs at 24 ticks a quarter note, internally everything counts in finer subticks
+//so swing and nudges still have room to move when a step is only a tick or two long
+pub const MIDI_PPQN:usize = 24;
+pub const SUBTICKS:usize = 4;
+pub const PPQN:usize = MIDI_PPQN * SUBTICKS;
+
+//everything is in 4/4 for now
+pub const BEATS_PER_BAR:usize = 4;
+pub const BAR_TICKS:usize = PPQN * BEATS_PER_BAR;
+
+//song position pointers count in sixteenths
+pub const SPP_TICKS:usize = PPQN / 4;
@@ -5,7 +5,13 @@ use serde::{Serialize,Deserialize};
 
 use crate::sequence_player;
 use crate::sequence_player::Player;
-use crate::sequence::{Trigger,Seq};
+use crate::scale;
+use crate::song::{self,SongEntry};
+use crate::scene::Scene;
+use crate::quantize::Quantize;
+use crate::midi_sink::MidiSink;
+use crate::arp::{self,Arp,ArpPattern};
+use crate::sequence::{self,Seq,Pattern,Condition,Direction};
 
 use std::fs::{File,OpenOptions};
 use std::io::BufReader;
@@ -22,11 +28,91 @@ struct NoteConfig
 	vel:u8
 }
 
+#[derive(Serialize,Deserialize)]
+struct KeyRangeConfig
+{
+	lo:u8,
+	hi:u8,
+	root:u8
+}
+
+#[derive(Serialize,Deserialize)]
+struct ArpConfig
+{
+	pattern:String,
+	octaves:u8
+}
+
+#[derive(Serialize,Deserialize)]
+struct NoteRangeConfig
+{
+	lo:u8,
+	hi:u8
+}
+
+#[derive(Serialize,Deserialize)]
+struct SceneSlotConfig
+{
+	slot:usize,
+
+	#[serde(default)]
+	hold:bool
+}
+
+#[derive(Serialize,Deserialize)]
+struct SceneConfig
+{
+	name:String,
+	slots:Vec<SceneSlotConfig>
+}
+
+#[derive(Serialize,Deserialize)]
+struct SongSlotConfig
+{
+	slot:usize,
+
+	#[serde(default)]
+	pattern:usize
+}
+
+#[derive(Serialize,Deserialize)]
+struct SongEntryConfig
+{
+	slots:Vec<SongSlotConfig>,
+	bars:usize,
+
+	#[serde(default="def_repeats")]
+	repeats:usize,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	jump:Option<usize>
+}
+
 #[derive(Serialize,Deserialize)]
 struct StepConfig
 {
+	//single notes from before steps held chords, only read now
+	#[serde(default,skip_serializing)]
 	note:Option<NoteConfig>,
+
+	#[serde(default)]
+	notes:Vec<NoteConfig>,
 	hold:bool,
+
+	#[serde(default="def_gate")]
+	gate:u16,
+
+	#[serde(default="def_prob")]
+	prob:u8,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	cond:Option<String>,
+
+	#[serde(default="def_ratchet")]
+	ratchet:u8,
+
+	#[serde(default)]
+	nudge:i16
 }
 
 #[derive(Serialize,Deserialize)]
@@ -36,26 +122,149 @@ struct SeqConfig
 	channel:u8,
 	tick_rate:usize,
 	port:Option<usize>,
+
+	//a single pattern from before slots had banks, loaded as the first one
+	#[serde(default,skip_serializing)]
 	steps:Vec<StepConfig>,
-	hold:bool
+
+	#[serde(default)]
+	patterns:Vec<Vec<StepConfig>>,
+
+	#[serde(default)]
+	pattern:usize,
+
+	hold:bool,
+
+	#[serde(default)]
+	swing:u8,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	direction:Option<String>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	key_range:Option<KeyRangeConfig>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	scale:Option<String>,
+
+	#[serde(default)]
+	scale_root:u8,
+
+	//arps need a key_range to get their notes from
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	arp:Option<ArpConfig>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	launch_quantize:Option<String>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	stop_quantize:Option<String>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	choke_group:Option<u8>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	mute_group:Option<u8>,
+
+	#[serde(default)]
+	muted:bool,
+
+	#[serde(default)]
+	soloed:bool
 }
 
 #[derive(Serialize,Deserialize,Copy,Clone)]
 pub struct MidiInConfig
 {
 	pub port_num:usize,
-	pub ticks:bool
+	pub ticks:bool,
+
+	//switch to the internal clock, starting at the last tempo heard, if the clock goes away
+	#[serde(default)]
+	pub fallback:bool
+}
+
+//older configs just list the port numbers
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OutPortEntry
+{
+	Num(usize),
+	Full
+	{
+		port_num:usize,
+
+		#[serde(default)]
+		clock:bool
+	}
+}
+
+#[derive(Serialize,Deserialize,Copy,Clone)]
+#[serde(from="OutPortEntry")]
+pub struct MidiOutConfig
+{
+	pub port_num:usize,
+	pub clock:bool //send clock, start/stop/continue and song position
+}
+
+impl From<OutPortEntry> for MidiOutConfig
+{
+	fn from(entry : OutPortEntry) -> MidiOutConfig
+	{
+		match entry
+		{
+			OutPortEntry::Num(port_num) => MidiOutConfig{port_num,clock:false},
+			OutPortEntry::Full{port_num,clock} => MidiOutConfig{port_num,clock}
+		}
+	}
 }
 
 #[derive(Serialize,Deserialize)]
 pub struct Config
 {
 	pub in_ports:Vec<MidiInConfig>,
-	pub out_ports:Vec<usize>,
+	pub out_ports:Vec<MidiOutConfig>,
 	seqs:Vec<SeqConfig>,
 
 	#[serde(default="def_bpm")]
-	pub bpm:f64
+	pub bpm:f64,
+
+	#[serde(default)]
+	pub seed:u64,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	swing:Option<u8>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	pattern_cc:Option<u8>,
+
+	//the arrangement, in playing order
+	#[serde(default,skip_serializing_if="Vec::is_empty")]
+	song:Vec<SongEntryConfig>,
+
+	#[serde(default,skip_serializing_if="Vec::is_empty")]
+	scenes:Vec<SceneConfig>,
+
+	//notes in here recall scenes from the bottom up instead of launching slots
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	scene_notes:Option<NoteRangeConfig>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	scene_cc:Option<u8>,
+
+	//the value of these picks the slot to mute or solo
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	mute_cc:Option<u8>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	solo_cc:Option<u8>,
+
+	//immediate, 16th, beat, bar or a number of midi clock ticks
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	launch_quantize:Option<String>,
+
+	#[serde(default,skip_serializing_if="Option::is_none")]
+	stop_quantize:Option<String>
 }
 
 //this has to be a function for some reason
@@ -64,6 +273,26 @@ fn def_bpm() -> f64
 	120.0
 }
 
+fn def_gate() -> u16
+{
+	sequence::GATE_FULL
+}
+
+fn def_prob() -> u8
+{
+	sequence::PROB_MAX
+}
+
+fn def_ratchet() -> u8
+{
+	1
+}
+
+fn def_repeats() -> usize
+{
+	1
+}
+
 //=============================================================================
 // READING THE CONFIG
 //=============================================================================
@@ -77,6 +306,61 @@ pub fn read_config(path:&str) -> Result<Config,Box<dyn Error>>
   Ok(conf)
 }
 
+fn parse_quantize(txt : &Option<String>, what : &str) -> Result<Option<Quantize>,ConfError>
+{
+	match txt
+	{
+		Some(txt) => Quantize::parse(txt).map(Some).ok_or_else(|| ConfError::new(format!("{} has a bad quantize {}",what,txt))),
+		None => Ok(None)
+	}
+}
+
+fn set_pattern(i:usize, pnum:usize, steps : &[StepConfig], nudge_limit:i16, pattern: &mut Pattern) -> Result<(),ConfError>
+{
+	if steps.len() > sequence::SEQ_MAX
+	{
+		return Err(ConfError::new(format!("sequence {} pattern {} is too long",i,pnum)))
+	}
+
+	pattern.length = steps.len();
+
+	for (stepnum,step) in steps.iter().enumerate()
+	{
+		if step.notes.len() > sequence::STEP_VOICES
+		{
+			return Err(ConfError::new(format!("sequence {} pattern {} step {} has too many notes",i,pnum,stepnum)))
+		}
+
+		let target_step = &mut pattern.steps[stepnum];
+
+		target_step.clear();
+
+		if let Some(NoteConfig{nn,vel}) = step.note
+		{
+			target_step.add_note(nn,vel);
+		}
+
+		for NoteConfig{nn,vel} in step.notes.iter()
+		{
+			target_step.add_note(*nn,*vel);
+		}
+
+		target_step.hold = step.hold;
+		target_step.gate = step.gate.clamp(sequence::GATE_MIN,sequence::GATE_MAX);
+		target_step.prob = std::cmp::min(step.prob,sequence::PROB_MAX);
+		target_step.ratchet = step.ratchet.clamp(1,sequence::RATCHET_MAX);
+		target_step.nudge = step.nudge.clamp(-nudge_limit,nudge_limit);
+
+		target_step.cond = match &step.cond
+		{
+			Some(txt) => Condition::parse(txt).ok_or_else(|| ConfError::new(format!("sequence {} pattern {} step {} has a bad condition {}",i,pnum,stepnum,txt)))?,
+			None => Condition::Always
+		};
+	}
+
+	Ok(())
+}
+
 fn set_seq(i:usize, entry : &SeqConfig, player: &mut Player) -> Result<(),ConfError>
 {
 	if entry.midi_map > 127
@@ -89,35 +373,97 @@ fn set_seq(i:usize, entry : &SeqConfig, player: &mut Player) -> Result<(),ConfEr
 		return Err(ConfError::new(format!("sequence {} has out of range channel",i)))
 	}
 
-	if entry.steps.len() > 64
+	if entry.tick_rate < sequence::TICK_RATE_MIN || entry.tick_rate > sequence::TICK_RATE_MAX
+	{
+		return Err(ConfError::new(format!("sequence {} has out of range tick_rate",i)))
+	}
+
+	if entry.patterns.len() > sequence::BANK_SIZE || entry.pattern >= sequence::BANK_SIZE
 	{
-		return Err(ConfError::new(format!("sequence {} has too long a sequence",i)))	
+		return Err(ConfError::new(format!("sequence {} has too many patterns",i)))
+	}
+
+	if let Some(KeyRangeConfig{lo,hi,root}) = entry.key_range
+	{
+		if lo > hi || hi > 127 || root > 127
+		{
+			return Err(ConfError::new(format!("sequence {} has a bad key range",i)))
+		}
 	}
 
 	let seq = &mut player.midi_map[entry.midi_map];
 
 	seq.channel = entry.channel;
 	seq.ticks_per_step = entry.tick_rate;
-	seq.length = entry.steps.len();
 	seq.hold = entry.hold;
+	seq.swing = std::cmp::min(entry.swing,sequence::SWING_MAX);
 
+	if let Some(KeyRangeConfig{lo,hi,root}) = entry.key_range
+	{
+		seq.key_range = Some((lo,hi));
+		seq.root = root;
+	}
 
-	for (stepnum,step) in entry.steps.iter().enumerate()
+	seq.scale = match &entry.scale
 	{
-		let target_step = &mut seq.steps[stepnum];
+		Some(txt) => Some(scale::parse(txt).ok_or_else(|| ConfError::new(format!("sequence {} has a bad scale {}",i,txt)))?),
+		None => None
+	};
+
+	seq.scale_root = entry.scale_root % 12;
 
-		target_step.trig = if let Some(NoteConfig{nn,vel}) = step.note
+	if let Some(ArpConfig{pattern,octaves}) = &entry.arp
+	{
+		if entry.key_range.is_none()
 		{
-			Trigger::On(nn,vel)
+			return Err(ConfError::new(format!("sequence {} has an arp but no key range",i)))
 		}
-		else 
+
+		let pattern = ArpPattern::parse(pattern).ok_or_else(|| ConfError::new(format!("sequence {} has a bad arp pattern {}",i,pattern)))?;
+
+		let mut seq_arp = Arp::new(pattern);
+		seq_arp.octaves = (*octaves).clamp(1,arp::OCTAVES_MAX);
+		seq.arp = Some(seq_arp);
+	}
+
+	for group in [entry.choke_group,entry.mute_group].iter().flatten()
+	{
+		if *group == 0 || *group > sequence::GROUP_MAX
 		{
-			Trigger::Off
-		};
+			return Err(ConfError::new(format!("sequence {} has an out of range group",i)))
+		}
+	}
 
-		target_step.hold = step.hold;
+	seq.choke_group = entry.choke_group;
+	seq.mute_group = entry.mute_group;
+	seq.muted = entry.muted;
+	seq.soloed = entry.soloed;
+
+	seq.launch_quantize = parse_quantize(&entry.launch_quantize,&format!("sequence {}",i))?;
+	seq.stop_quantize = parse_quantize(&entry.stop_quantize,&format!("sequence {}",i))?;
+
+	seq.direction = match &entry.direction
+	{
+		Some(txt) => Direction::parse(txt).ok_or_else(|| ConfError::new(format!("sequence {} has a bad direction {}",i,txt)))?,
+		None => Direction::Forward
+	};
+
+
+	let nudge_limit = seq.nudge_limit();
+
+	if !entry.steps.is_empty()
+	{
+		set_pattern(i,0,&entry.steps,nudge_limit,&mut seq.patterns[0])?;
+	}
+
+	for (pnum,steps) in entry.patterns.iter().enumerate()
+	{
+		set_pattern(i,pnum,steps,nudge_limit,&mut seq.patterns[pnum])?;
 	}
 
+	seq.pattern = entry.pattern;
+	seq.edit_pattern = entry.pattern;
+
 	if let Some(pnum) = entry.port
 	{
 		seq.port = pnum;
@@ -126,19 +472,113 @@ fn set_seq(i:usize, entry : &SeqConfig, player: &mut Player) -> Result<(),ConfEr
 	Ok(())
 }
 
-pub fn apply_config(player: &mut Player, conf: & Config) -> Result<(),ConfError>
+fn set_song(conf : &Config, player: &mut Player) -> Result<(),ConfError>
 {
+	for (i,entry) in conf.song.iter().enumerate()
+	{
+		if entry.bars == 0 || entry.bars > song::BARS_MAX || entry.repeats == 0 || entry.repeats > song::REPEATS_MAX
+		{
+			return Err(ConfError::new(format!("song entry {} has a bad length",i)))
+		}
+
+		if let Some(j) = entry.jump
+		{
+			if j >= conf.song.len()
+			{
+				return Err(ConfError::new(format!("song entry {} jumps past the end",i)))
+			}
+		}
+
+		let mut slots = vec![];
+
+		for SongSlotConfig{slot,pattern} in entry.slots.iter()
+		{
+			if *slot > 127 || *pattern >= sequence::BANK_SIZE
+			{
+				return Err(ConfError::new(format!("song entry {} has a bad slot",i)))
+			}
+
+			slots.push((*slot,*pattern));
+		}
+
+		player.song.entries.push(SongEntry{slots,bars:entry.bars,repeats:entry.repeats,jump:entry.jump});
+	}
+
+	Ok(())
+}
+
+fn set_scenes(conf : &Config, player: &mut Player) -> Result<(),ConfError>
+{
+	for (i,scene) in conf.scenes.iter().enumerate()
+	{
+		let mut new_scene = Scene::new(scene.name.clone());
+
+		for SceneSlotConfig{slot,hold} in scene.slots.iter()
+		{
+			if *slot > 127
+			{
+				return Err(ConfError::new(format!("scene {} has a bad slot",i)))
+			}
+
+			new_scene.slots.push((*slot,*hold));
+		}
+
+		player.scenes.push(new_scene);
+	}
+
+	if let Some(NoteRangeConfig{lo,hi}) = conf.scene_notes
+	{
+		if lo > hi || hi > 127
+		{
+			return Err(ConfError::new(format!("scene notes {}..{} are a bad range",lo,hi)))
+		}
+
+		player.scene_notes = Some((lo,hi));
+	}
+
+	player.scene_cc = conf.scene_cc;
+	player.mute_cc = conf.mute_cc;
+	player.solo_cc = conf.solo_cc;
+
+	Ok(())
+}
+
+//the real ports, anything that doesn't want hardware passes its own opener to apply_config
+pub fn open_midi_port(port_num: usize) -> Result<Box<dyn MidiSink>,ConfError>
+{
+	let midi_out = MidiOutput::new("WOWOOWOOOWOOW").map_err(|e| ConfError::new(format!("no midi output: {}",e)))?;
+	let con = midi_out.connect(port_num,"some thing").map_err(|e| ConfError::new(format!("couldn't open out port {}: {}",port_num,e)))?;
+
+	Ok(Box::new(con))
+}
+
+pub fn apply_config(player: &mut Player, conf: & Config, open_port: &mut dyn FnMut(usize) -> Result<Box<dyn MidiSink>,ConfError>) -> Result<(),ConfError>
+{
+	player.set_seed(conf.seed);
+	player.tempo.set(conf.bpm);
+	player.set_swing(conf.swing);
+	player.pattern_cc = conf.pattern_cc;
+	player.launch_quantize = parse_quantize(&conf.launch_quantize,"launch")?.unwrap_or(Quantize::Sixteenth);
+	player.stop_quantize = parse_quantize(&conf.stop_quantize,"stop")?.unwrap_or(Quantize::Immediate);
+
 	for (i,entry) in conf.seqs.iter().enumerate()
 	{
 		set_seq(i,entry,player)?;
 	}
 
-	for (i,pnum) in conf.out_ports.iter().enumerate()
+	set_song(conf,player)?;
+	set_scenes(conf,player)?;
+
+	for (i,MidiOutConfig{port_num,clock}) in conf.out_ports.iter().enumerate()
 	{
-		let midi_out = MidiOutput::new("WOWOOWOOOWOOW").unwrap();
-		let con = midi_out.connect(*pnum,"some thing").unwrap();
+		let con = open_port(*port_num)?;
+
+		if *clock
+		{
+			player.clock_ports.push(player.out_connections.len());
+		}
 
-		player.out_connections.push((*pnum,con));
+		player.out_connections.push((*port_num,con));
 	}
 
 	Ok(())
@@ -155,37 +595,106 @@ fn seq_to_config(i:usize,seq: &Seq) -> Result<SeqConfig,ConfError>
 		channel:seq.channel,
 		tick_rate:seq.ticks_per_step,
 		steps:vec![],
+		patterns:vec![],
+		pattern:seq.pattern,
 		hold:seq.hold,
+		swing:seq.swing,
+		direction:match seq.direction
+		{
+			Direction::Forward => None,
+			d => Some(d.to_string())
+		},
+		key_range:seq.key_range.map(|(lo,hi)| KeyRangeConfig{lo,hi,root:seq.root}),
+		scale:seq.scale.map(scale::name),
+		scale_root:seq.scale_root,
+		arp:seq.arp.map(|a| ArpConfig{pattern:a.pattern.to_string(),octaves:a.octaves}),
+		launch_quantize:seq.launch_quantize.map(|q| q.to_string()),
+		stop_quantize:seq.stop_quantize.map(|q| q.to_string()),
+		choke_group:seq.choke_group,
+		mute_group:seq.mute_group,
+		muted:seq.muted,
+		soloed:seq.soloed,
 		port:Some(seq.port)
 	};
 
-	for step in seq.steps[0..seq.length].iter()
+	//empty patterns on the end don't need saving
+	let used = seq.patterns.iter().rposition(|p| p.length > 0).map_or(0,|n| n + 1);
+
+	for pattern in seq.patterns[0..used].iter()
 	{
-		let note_conf = match step.trig
+		let mut steps = vec![];
+
+		for step in pattern.steps[0..pattern.length].iter()
 		{
-			Trigger::On(nn,vel) => StepConfig{note:Some(NoteConfig{nn:nn,vel:vel}),hold:step.hold},
-			Trigger::Off => StepConfig{note:None,hold:step.hold}
-		};
+			let note_conf = StepConfig
+			{
+				note:None,
+				notes:step.notes().map(|(nn,vel)| NoteConfig{nn,vel}).collect(),
+				hold:step.hold,
+				gate:step.gate,
+				prob:step.prob,
+				ratchet:step.ratchet,
+				nudge:step.nudge,
+				cond:match step.cond
+				{
+					Condition::Always => None,
+					c => Some(c.to_string())
+				}
+			};
+
+			steps.push(note_conf);
+		}
 
-		s_conf.steps.push(note_conf);
+		s_conf.patterns.push(steps);
 	}
 
 	Ok(s_conf)
 }
 
-fn to_config(player: &Player,bpm:f64,in_ports : &[MidiInConfig]) -> Result<Config,ConfError>
+fn to_config(player: &Player,in_ports : &[MidiInConfig]) -> Result<Config,ConfError>
 {
 	let mut conf = Config
 	{
-		bpm:bpm,
+		bpm:player.tempo.get(),
+		seed:player.seed,
+		swing:player.swing,
+		pattern_cc:player.pattern_cc,
 		in_ports:vec![],
 		out_ports:vec![],
-		seqs:vec![]
+		seqs:vec![],
+		song:vec![],
+		scenes:vec![],
+		scene_notes:player.scene_notes.map(|(lo,hi)| NoteRangeConfig{lo,hi}),
+		scene_cc:player.scene_cc,
+		mute_cc:player.mute_cc,
+		solo_cc:player.solo_cc,
+		launch_quantize:Some(player.launch_quantize.to_string()),
+		stop_quantize:Some(player.stop_quantize.to_string())
 	};
 
-	for (i,_port) in player.out_connections.iter()
+	for scene in player.scenes.iter()
+	{
+		conf.scenes.push(SceneConfig
+		{
+			name:scene.name.clone(),
+			slots:scene.slots.iter().map(|(slot,hold)| SceneSlotConfig{slot:*slot,hold:*hold}).collect()
+		});
+	}
+
+	for entry in player.song.entries.iter()
+	{
+		conf.song.push(SongEntryConfig
+		{
+			slots:entry.slots.iter().map(|(slot,pattern)| SongSlotConfig{slot:*slot,pattern:*pattern}).collect(),
+			bars:entry.bars,
+			repeats:entry.repeats,
+			jump:entry.jump
+		});
+	}
+
+	for (idx,(i,_port)) in player.out_connections.iter().enumerate()
 	{
-		conf.out_ports.push(*i);
+		conf.out_ports.push(MidiOutConfig{port_num:*i,clock:player.clock_ports.contains(&idx)});
 	}
 
 	for (i,seq) in player.midi_map.iter().enumerate()
@@ -202,11 +711,11 @@ fn to_config(player: &Player,bpm:f64,in_ports : &[MidiInConfig]) -> Result<Confi
 	Ok(conf)
 }
 
-pub fn save_config(path:&str, player: &Player,bpm:f64,in_ports: &[MidiInConfig]) -> Result<(),Box<dyn Error>>
+pub fn save_config(path:&str, player: &Player,in_ports: &[MidiInConfig]) -> Result<(),Box<dyn Error>>
 {
 	let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
 
-	let conf = to_config(player,bpm,in_ports)?;
+	let conf = to_config(player,in_ports)?;
 
 	let conf_str = serde_json::to_string(&conf)?;
 
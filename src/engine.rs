@@ -0,0 +1,273 @@
+use crate::sequence::{PlayState,Seq,Pattern,Direction};
+use crate::arp::Arp;
+use crate::sequence_player::Player;
+use crate::screens::Screen;
+use crate::input_types::Input;
+use crate::scheduler::Jitter;
+use crate::quantize::Quantize;
+use crate::song::{SongState,SongEntry};
+use crate::tempo::Tempo;
+use crate::follower::FollowState;
+
+use std::sync::{Arc,Mutex,mpsc::{Receiver,SyncSender}};
+use std::time::Instant;
+
+//the play screen only has room for this many running slots
+pub const SHOWN_SLOTS:usize = 16;
+
+//just what the play screen lists for a running slot
+#[derive(Copy,Clone)]
+pub struct SlotView
+{
+	pub slot : usize,
+	pub state : PlayState,
+	pub hold : bool,
+	pub pattern : usize,
+	pub queued : Option<usize>,
+	pub transpose : Option<i16>,
+	pub choke_group : Option<u8>,
+	pub mute_group : Option<u8>,
+	pub muted : bool,
+	pub soloed : bool,
+	pub silenced : bool
+}
+
+impl SlotView
+{
+	fn new(slot : usize, seq : &Seq) -> SlotView
+	{
+		SlotView
+		{
+			slot,
+			state : seq.state,
+			hold : seq.hold,
+			pattern : seq.pattern,
+			queued : seq.queued,
+			transpose : seq.key_range.map(|_| seq.transpose()),
+			choke_group : seq.choke_group,
+			mute_group : seq.mute_group,
+			muted : seq.muted,
+			soloed : seq.soloed,
+			silenced : seq.silenced
+		}
+	}
+}
+
+//the slot being edited, with only the pattern on screen rather than the whole bank
+pub struct EditView
+{
+	pub editing : Pattern,
+	pub edit_pattern : usize,
+	pub edit_step : usize,
+	pub pattern : usize,
+	pub position : usize,
+	pub queued : Option<usize>,
+
+	pub ticks_per_step : usize,
+	pub channel : u8,
+	pub port : usize,
+	pub swing : u8,
+	pub global_swing : Option<u8>,
+	pub direction : Direction,
+	pub launch_quantize : Option<Quantize>,
+	pub stop_quantize : Option<Quantize>,
+	pub choke_group : Option<u8>,
+	pub mute_group : Option<u8>,
+	pub muted : bool,
+	pub soloed : bool,
+
+	pub key_range : Option<(u8,u8)>,
+	pub root : u8,
+	pub transpose : i16,
+	pub scale : Option<u16>,
+	pub scale_root : u8,
+	pub arp : Option<Arp>
+}
+
+impl EditView
+{
+	fn new(seq : &Seq) -> EditView
+	{
+		EditView
+		{
+			editing : *seq.editing(),
+			edit_pattern : seq.edit_pattern,
+			edit_step : seq.edit_step,
+			pattern : seq.pattern,
+			position : seq.position,
+			queued : seq.queued,
+			ticks_per_step : seq.ticks_per_step,
+			channel : seq.channel,
+			port : seq.port,
+			swing : seq.swing,
+			global_swing : seq.global_swing,
+			direction : seq.direction,
+			launch_quantize : seq.launch_quantize,
+			stop_quantize : seq.stop_quantize,
+			choke_group : seq.choke_group,
+			mute_group : seq.mute_group,
+			muted : seq.muted,
+			soloed : seq.soloed,
+			key_range : seq.key_range,
+			root : seq.root,
+			transpose : seq.transpose(),
+			scale : seq.scale,
+			scale_root : seq.scale_root,
+			arp : seq.arp
+		}
+	}
+}
+
+pub struct SongView
+{
+	pub state : SongState,
+	pub entry : usize,
+	pub bar : usize,
+	pub repeat : usize,
+	pub entries : Arc<Vec<SongEntry>>
+}
+
+impl SongView
+{
+	pub fn is_playing(&self) -> bool
+	{
+		self.state != SongState::Stopped
+	}
+}
+
+//the bits that hardly ever change are only copied again when they do,
+//every snapshot in between shares them
+#[derive(Default)]
+struct Shared
+{
+	scene_names : Arc<Vec<String>>,
+	song_entries : Arc<Vec<SongEntry>>
+}
+
+impl Shared
+{
+	fn refresh(&mut self, player : &Player)
+	{
+		if !self.scene_names.iter().eq(player.scenes.iter().map(|s| &s.name))
+		{
+			self.scene_names = Arc::new(player.scenes.iter().map(|s| s.name.clone()).collect());
+		}
+
+		if *self.song_entries != player.song.entries
+		{
+			self.song_entries = Arc::new(player.song.entries.clone());
+		}
+	}
+}
+
+//a copy of everything the ui draws, so drawing never has to touch the player
+pub struct Snapshot
+{
+	pub screen : Screen,
+
+	pub running : bool,
+	pub fill : bool,
+	pub swing : Option<u8>,
+	pub tempo : Arc<Tempo>,
+	pub follow : Option<Arc<FollowState>>,
+	pub launch_quantize : Quantize,
+	pub stop_quantize : Quantize,
+
+	pub song : SongView,
+	pub scenes : Arc<Vec<String>>,
+	pub scene_queued : Option<usize>,
+
+	pub slots : [Option<SlotView>;SHOWN_SLOTS],
+	pub edit : EditView
+}
+
+impl Snapshot
+{
+	fn new(player : &Player, screen : &Screen, shared : &Shared) -> Snapshot
+	{
+		let mut slots = [None;SHOWN_SLOTS];
+
+		let running = player.midi_map.iter()
+		.enumerate()
+		.filter(|(_,s)| s.state != PlayState::Off)
+		.take(SHOWN_SLOTS);
+
+		for (view,(i,s)) in slots.iter_mut().zip(running)
+		{
+			*view = Some(SlotView::new(i,s));
+		}
+
+		Snapshot
+		{
+			screen : screen.clone(),
+			running : player.running,
+			fill : player.fill,
+			swing : player.swing,
+			tempo : player.tempo.clone(),
+			follow : player.follow.clone(),
+			launch_quantize : player.launch_quantize,
+			stop_quantize : player.stop_quantize,
+			song : SongView
+			{
+				state : player.song.state,
+				entry : player.song.entry,
+				bar : player.song.bar,
+				repeat : player.song.repeat,
+				entries : shared.song_entries.clone()
+			},
+			scenes : shared.scene_names.clone(),
+			scene_queued : player.scene_queued,
+			slots,
+			edit : EditView::new(&player.midi_map[screen.current_edit()])
+		}
+	}
+
+	pub fn draw(&self)
+	{
+		self.screen.draw(self);
+	}
+}
+
+//swaps in the newest snapshot and pokes the ui, if it's still busy drawing the last one
+//it picks this up when it's done and anything in between is skipped
+fn publish(player : &Player, screen : &Screen, shared : &mut Shared, latest : &Mutex<Option<Snapshot>>, wake : &SyncSender<()>)
+{
+	shared.refresh(player);
+
+	let snap = Snapshot::new(player,screen,shared);
+	*latest.lock().unwrap() = Some(snap);
+	let _ = wake.try_send(());
+}
+
+//the timing critical side, ticks, midi and keys all land here and the player
+//is only ever touched from this thread, the terminal never is
+pub fn run(player : &mut Player, screen : &mut Screen, rx : &Receiver<Input>, latest : &Mutex<Option<Snapshot>>, wake : SyncSender<()>) -> Jitter
+{
+	//how long ticks sit waiting before the engine gets to them
+	let mut dispatch = Jitter::default();
+	let mut shared = Shared::default();
+
+	publish(player,screen,&mut shared,latest,&wake);
+
+	for event in rx.iter()
+	{
+		if let Input::Tick(stamp) = event
+		{
+			dispatch.record(Instant::now().saturating_duration_since(stamp));
+		}
+
+		let (redraw,quit) = screen.input(player,event);
+
+		if quit
+		{
+			break;
+		}
+
+		if redraw
+		{
+			publish(player,screen,&mut shared,latest,&wake);
+		}
+	}
+
+	dispatch
+}
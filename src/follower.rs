@@ -0,0 +1,300 @@
+use crate::clock;
+use crate::input_types::Input;
+use crate::scheduler::{self,Jitter};
+use crate::tempo::Tempo;
+
+use std::sync::{atomic::{Ordering,AtomicBool},mpsc::{Receiver,Sender,RecvTimeoutError},Arc};
+use std::thread::{self,JoinHandle};
+use std::time::{Duration,Instant};
+
+//how hard the estimates chase what's coming in, lower is smoother but slower to follow
+const PERIOD_GAIN:f64 = 0.1;
+const PHASE_GAIN:f64 = 0.25;
+
+//ticks go out this far behind the smoothed clock, so one arriving a bit late is still on time
+const LATENCY:Duration = Duration::from_millis(2);
+
+//nothing for this long and the clock is gone, about 20 bpm
+const LOST_AFTER:Duration = Duration::from_millis(500);
+
+//what the screen gets to see
+pub struct FollowState
+{
+	pub tempo: Tempo,
+	pub locked: AtomicBool,
+	pub falling_back: AtomicBool //lost the clock and running on our own at the player's tempo
+}
+
+impl FollowState
+{
+	pub fn new() -> FollowState
+	{
+		FollowState{tempo: Tempo::new(120.0),locked: AtomicBool::new(false),falling_back: AtomicBool::new(false)}
+	}
+}
+
+struct Follower
+{
+	state: Arc<FollowState>,
+	tx: Sender<Input>,
+	fallback: bool,
+	tempo: Arc<Tempo>, //the player's, what the internal clock runs at once we've fallen back
+	internal: Option<(Arc<AtomicBool>,JoinHandle<Jitter>)>,
+
+	period: Option<f64>, //seconds per midi clock
+	last_arrival: Option<Instant>,
+	phase: Instant, //where the smoothed clock put the last tick
+
+	pending: usize, //subticks left to go for the last clock
+	spacing: Duration, //between them
+	next: Instant,
+
+	jitter: Jitter
+}
+
+impl Follower
+{
+	fn emit(&mut self, when : Instant) -> bool
+	{
+		self.pending -= 1;
+		self.next = when + self.spacing;
+		self.tx.send(Input::Tick(when)).is_ok()
+	}
+
+	//the clock's back, so our own one has to go before anything else is sent
+	fn stop_internal(&mut self)
+	{
+		if let Some((hangup,thrd)) = self.internal.take()
+		{
+			hangup.store(true,Ordering::Relaxed);
+			let _ = thrd.join();
+		}
+
+		self.state.falling_back.store(false,Ordering::Relaxed);
+	}
+
+	//a clock coming in sets the phase and period, anything left over from the last one is
+	//squeezed in with this one's so we never end up a tick short or over, or send a burst
+	fn on_clock(&mut self, stamp : Instant)
+	{
+		self.stop_internal();
+
+		//more than a clock behind means we've stalled, those are just dropped
+		let leftover = std::cmp::min(self.pending,clock::SUBTICKS);
+
+		if let Some(last) = self.last_arrival
+		{
+			let interval = (stamp - last).as_secs_f64();
+
+			self.period = match self.period
+			{
+				None => Some(interval),
+				Some(p) =>
+				{
+					self.jitter.record(Duration::from_secs_f64((interval - p).abs()));
+
+					//way off means a tempo jump or a restart, take it as it is
+					if interval > p * 2.0 || interval < p * 0.5
+					{
+						Some(interval)
+					}
+					else 
+					{
+						Some(p + (interval - p) * PERIOD_GAIN)
+					}
+				}
+			};
+		}
+
+		self.phase = match (self.period,self.state.locked.load(Ordering::Relaxed))
+		{
+			(Some(p),true) =>
+			{
+				let predicted = self.phase + Duration::from_secs_f64(p);
+
+				if stamp > predicted
+				{
+					predicted + (stamp - predicted).mul_f64(PHASE_GAIN)
+				}
+				else 
+				{
+					predicted - (predicted - stamp).mul_f64(PHASE_GAIN)
+				}
+			}
+			_ => stamp
+		};
+
+		self.last_arrival = Some(stamp);
+
+		if let Some(p) = self.period
+		{
+			self.state.tempo.set(60.0 / (p * clock::MIDI_PPQN as f64));
+			self.state.locked.store(true,Ordering::Relaxed);
+		}
+
+		self.pending = leftover + clock::SUBTICKS;
+		self.spacing = Duration::from_secs_f64(self.period.unwrap_or(0.02) / self.pending as f64);
+		self.next = self.phase + LATENCY;
+	}
+
+	//gone quiet, either stop until it comes back or hand over to the internal clock,
+	//starting from the last tempo we heard, it goes wherever the player's tempo is taken after that
+	fn on_lost(&mut self)
+	{
+		self.state.locked.store(false,Ordering::Relaxed);
+		self.last_arrival = None;
+		self.pending = 0;
+
+		if !self.fallback || self.internal.is_some()
+		{
+			return
+		}
+
+		if let Some(p) = self.period
+		{
+			self.tempo.set(60.0 / (p * clock::MIDI_PPQN as f64));
+
+			let hangup = Arc::new(AtomicBool::new(false));
+			let thrd = scheduler::spawn(self.tempo.clone(),hangup.clone(),self.tx.clone());
+			self.internal = Some((hangup,thrd));
+			self.state.falling_back.store(true,Ordering::Relaxed);
+		}
+	}
+}
+
+pub fn spawn(clock_rx : Receiver<Instant>, tx : Sender<Input>, state : Arc<FollowState>, tempo : Arc<Tempo>, fallback : bool, hangup : Arc<AtomicBool>) -> JoinHandle<Jitter>
+{
+	thread::spawn(move ||
+	{
+		let now = Instant::now();
+
+		let mut follower = Follower
+		{
+			state,
+			tx,
+			fallback,
+			tempo,
+			internal: None,
+			period: None,
+			last_arrival: None,
+			phase: now,
+			pending: 0,
+			spacing: Duration::ZERO,
+			next: now,
+			jitter: Jitter::default()
+		};
+
+		while !hangup.load(Ordering::Relaxed)
+		{
+			let now = Instant::now();
+
+			let timeout = if follower.pending > 0
+			{
+				follower.next.saturating_duration_since(now)
+			}
+			else 
+			{
+				LOST_AFTER
+			};
+
+			let ok = match clock_rx.recv_timeout(timeout)
+			{
+				Ok(stamp) =>
+				{
+					follower.on_clock(stamp);
+					true
+				}
+
+				Err(RecvTimeoutError::Timeout) if follower.pending > 0 =>
+				{
+					let when = follower.next;
+					follower.emit(when)
+				}
+
+				Err(RecvTimeoutError::Timeout) =>
+				{
+					follower.on_lost();
+					true
+				}
+
+				Err(RecvTimeoutError::Disconnected) => false
+			};
+
+			if !ok
+			{
+				break;
+			}
+		}
+
+		follower.stop_internal();
+		follower.jitter
+	})
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use std::sync::mpsc;
+
+	fn follower(fallback : bool, tx : Sender<Input>) -> Follower
+	{
+		let now = Instant::now();
+
+		Follower
+		{
+			state: Arc::new(FollowState::new()),
+			tx,
+			fallback,
+			tempo: Arc::new(Tempo::new(120.0)),
+			internal: None,
+			period: None,
+			last_arrival: None,
+			phase: now,
+			pending: 0,
+			spacing: Duration::ZERO,
+			next: now,
+			jitter: Jitter::default()
+		}
+	}
+
+	#[test]
+	fn leftover_subticks_are_spread_over_the_next_clock()
+	{
+		let (tx,rx) = mpsc::channel();
+		let mut follower = follower(false,tx);
+		let start = Instant::now();
+
+		follower.on_clock(start);
+		follower.on_clock(start + Duration::from_millis(20));
+		follower.pending = 2;
+		follower.on_clock(start + Duration::from_millis(40));
+
+		//nothing went out in a burst, the two left over wait their turn with the new four
+		assert!(rx.try_recv().is_err());
+		assert_eq!(follower.pending,6);
+		assert_eq!(follower.spacing,Duration::from_secs_f64(0.02 / 6.0));
+	}
+
+	#[test]
+	fn losing_the_clock_hands_over_to_the_internal_one()
+	{
+		let (tx,_rx) = mpsc::channel();
+		let mut follower = follower(true,tx);
+		let start = Instant::now();
+
+		//a clock every 100ms is 25 bpm
+		follower.on_clock(start);
+		follower.on_clock(start + Duration::from_millis(100));
+		follower.on_lost();
+
+		assert_eq!(follower.tempo.get(),25.0);
+		assert!(follower.internal.is_some());
+		assert!(follower.state.falling_back.load(Ordering::Relaxed));
+
+		follower.on_clock(Instant::now());
+
+		assert!(follower.internal.is_none());
+		assert!(!follower.state.falling_back.load(Ordering::Relaxed));
+	}
+}
@@ -3,12 +3,13 @@ use crate::midi_msg::MidiMessage;
 use termion::event::{Event,Key};
 use std::sync::mpsc::Sender;
 use std::io::Stdin;
+use std::time::Instant;
 
 pub enum Input
 {
 	Keyboard(Key),
 	Midi(MidiMessage),
-	Tick,
+	Tick(Instant), //when it was meant to happen
 	Quit
 }
 
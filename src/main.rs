@@ -4,17 +4,29 @@
 
 extern crate termion;
 extern crate midir;
-extern crate adi_clock;
 extern crate serde;
 extern crate serde_json;
 
+mod rng;
+mod clock;
+mod tempo;
+mod scheduler;
+mod follower;
+mod quantize;
+mod scale;
+mod arp;
+mod song;
+mod scene;
 mod sequence;
 mod note_lookup;
 mod midi_msg;
 mod input_types;
+mod midi_sink;
 mod out_port;
 mod sequence_player;
 mod screens;
+mod engine;
+mod render;
 mod config_file;
 
 use config_file::MidiInConfig;
@@ -37,43 +49,44 @@ use midir::{MidiInput,MidiInputConnection, MidiOutput, Ignore};
 use std::io::{Write,stdout, stdin};
 use std::error::Error;
 use std::thread;
-use std::sync::{atomic::{Ordering,AtomicBool},mpsc::{self,TryRecvError},Arc};
-use std::time::Duration;
+use std::sync::{atomic::{Ordering,AtomicBool},mpsc::{self,TryRecvError},Arc,Mutex};
+use std::time::{Duration,Instant};
 
-fn setup_seqs(file_path : Option<&str> ) -> Result<(sequence_player::Player,f64,Vec<MidiInConfig>), Box<dyn Error>> 
+fn setup_seqs(file_path : Option<&str> ) -> Result<(sequence_player::Player,Vec<MidiInConfig>), Box<dyn Error>> 
 {
 	let mut playo = sequence_player::Player::blank();
 
 	let real_path = file_path.unwrap_or("./conf.json");
 	let conf = config_file::read_config(real_path)?;
 
-	config_file::apply_config(& mut playo,&conf)?;
+	config_file::apply_config(& mut playo,&conf,&mut config_file::open_midi_port)?;
 
-	Ok((playo,conf.bpm,conf.in_ports))
+	Ok((playo,conf.in_ports))
 }
 
-fn save_conf(file_path : Option<&str>,player: &sequence_player::Player,bpm : f64, in_ports: &[config_file::MidiInConfig]) -> Result<(),Box<dyn Error>>
+fn save_conf(file_path : Option<&str>,player: &sequence_player::Player, in_ports: &[config_file::MidiInConfig]) -> Result<(),Box<dyn Error>>
 {
 	let real_path = file_path.unwrap_or("./conf.json");
 
-	config_file::save_config(real_path,player,bpm,in_ports)
+	config_file::save_config(real_path,player,in_ports)
 }
 
 fn real_main() -> Result<(), Box<dyn Error>>
 {
-	let (mut playo,bpm,in_ports) = setup_seqs(None)?;
+	let (mut playo,in_ports) = setup_seqs(None)?;
 
 	let _stdout = stdout().into_raw_mode().unwrap();
 
 	let hangup = Arc::new(AtomicBool::new(false));
 	let timer_hangup = hangup.clone();
 	
-	let (tx,rx) = mpsc::channel();
+	let (tx,mut rx) = mpsc::channel();
 
 	let key_tx = tx.clone();
 
 	let mut midi_in_connections : Vec<MidiInputConnection<()>> = vec![];
 	let mut external_ticks = false;
+	let mut follower_threads = vec![];
 
 	for midi_conf in in_ports.iter()
 	{
@@ -85,17 +98,20 @@ fn real_main() -> Result<(), Box<dyn Error>>
 			external_ticks = true;
 			midi_in.ignore(Ignore::Sysex | Ignore::ActiveSense);
 
+			//the follower smooths the clock out and turns it into our subticks
+			let (clock_tx,clock_rx) = mpsc::channel();
+			let state = playo.follow.get_or_insert_with(|| Arc::new(follower::FollowState::new())).clone();
+			follower_threads.push(follower::spawn(clock_rx,tx.clone(),state,playo.tempo.clone(),midi_conf.fallback,hangup.clone()));
+
 			midi_in.connect(midi_conf.port_num,"a midi port", move |_stamp,message,_|
 	  	{
 	  		let parsed = midi_msg::parse(message);
 
-	  		let out_msg = match parsed
+	  		match parsed
 	  		{
-	  			MidiMessage::Tick=> Input::Tick,
-	  			_=>Input::Midi(parsed)
+	  			MidiMessage::Tick => clock_tx.send(Instant::now()).unwrap(),
+	  			_=>midi_tx.send(Input::Midi(parsed)).unwrap()
 	  		};
-
-	  		midi_tx.send(out_msg).unwrap();
 	  	},()).unwrap()
 		}
 		else 
@@ -104,7 +120,12 @@ fn real_main() -> Result<(), Box<dyn Error>>
 			
 			midi_in.connect(midi_conf.port_num,"a midi port", move |_stamp,message,_|
 	  	{
-	  		midi_tx.send(Input::Midi(midi_msg::parse(message))).unwrap();
+	  		//transport only counts from the port we take the clock from
+	  		match midi_msg::parse(message)
+	  		{
+	  			MidiMessage::Start | MidiMessage::Continue | MidiMessage::Stop | MidiMessage::SongPosition(_,_) => (),
+	  			parsed => midi_tx.send(Input::Midi(parsed)).unwrap()
+	  		};
 	  	},()).unwrap()
 		};
 
@@ -117,17 +138,7 @@ fn real_main() -> Result<(), Box<dyn Error>>
 
 	let timer_thread = if !external_ticks
 	{
-		let spt = 1.0 / (24.0 * bpm / 60.0);
-		Some(thread::spawn(move ||
-		{
-			let mut timer = adi_clock::Timer::new(spt as f32);
-
-			while ! (timer_hangup.load(Ordering::Relaxed))
-			{
-				timer.wait();
-				tx.send(Input::Tick).unwrap();
-			}
-		}))
+		Some(scheduler::spawn(playo.tempo.clone(),timer_hangup,tx))
 	}
 	else 
 	{
@@ -148,37 +159,55 @@ fn real_main() -> Result<(), Box<dyn Error>>
 	let mut screen = Screen::new();
 
 	print!("{}",cursor::Hide);
-	screen.draw(&playo);
-	stdout().flush()?;
-	
-	for event in rx.iter()
-	{
-		let (redraw,quit) = screen.input(& mut playo,event);
 
-		if quit
-		{ 
-			break;
-		}
+	//the engine gets the player to itself, this thread just draws whatever it last handed over
+	let latest = Mutex::new(None);
+	let (wake_tx,wake_rx) = mpsc::sync_channel(1);
 
-		if redraw
+	let dispatch = thread::scope(|s| -> Result<scheduler::Jitter,Box<dyn Error>>
+	{
+		let (player,screen,rx,latest) = (&mut playo,&mut screen,&mut rx,&latest);
+		let engine_thread = s.spawn(move || engine::run(player,screen,rx,latest,wake_tx));
+
+		//the engine hanging up on quit is what ends this
+		for _ in wake_rx.iter()
 		{
-			screen.draw(&playo);
-			stdout().flush()?;
+			let snap = latest.lock().unwrap().take();
+
+			if let Some(snap) = snap
+			{
+				snap.draw();
+				stdout().flush()?;
+			}
 		}
-	}
+
+		Ok(engine_thread.join().unwrap())
+	})?;
 
 	hangup.store(true,Ordering::Relaxed);
 	
 	if let Some(thrd) = timer_thread
 	{
-		thrd.join().unwrap();
+		let jitter = thrd.join().unwrap();
+		eprintln!("clock: {}",jitter);
+	}
+
+	for thrd in follower_threads
+	{
+		let jitter = thrd.join().unwrap();
+		eprintln!("external clock: {}",jitter);
 	}
 
+	eprintln!("dispatch: {}",dispatch);
+
 	key_thread.join().unwrap();
 
+	playo.clock_stop();
+
 	//ok time to save the config
 
-	save_conf(None,&playo,bpm,&in_ports[..])?;
+	//the tempo goes back in the config as it was left
+	save_conf(None,&playo,&in_ports[..])?;
 
 	println!("{}{}{}",clear::All,cursor::Goto(1,1),cursor::Show);
 	Ok(())
@@ -187,7 +216,15 @@ fn real_main() -> Result<(), Box<dyn Error>>
 //this is so we can use the ?
 fn main()
 {
-	match real_main()
+	let args : Vec<String> = std::env::args().collect();
+
+	let result = match args.get(1).map(|a| a.as_str())
+	{
+		Some("render") => render::cli(&args[2..]),
+		_ => real_main()
+	};
+
+	match result
 	{
 		Ok(_) => (),
 		Err(err) => println!("It all went wrong: {}",err)
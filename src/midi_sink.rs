@@ -0,0 +1,95 @@
+use midir::MidiOutputConnection;
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc,Mutex};
+
+//a message that couldn't go out, whatever the sink behind it is
+#[derive(Debug)]
+pub struct SendError
+{
+	details: String
+}
+
+impl SendError
+{
+	pub fn new(msg: String) -> SendError
+	{
+		SendError{details: msg}
+	}
+}
+
+impl fmt::Display for SendError
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f,"{}",self.details)
+	}
+}
+
+impl Error for SendError {}
+
+//anywhere the player can send raw midi bytes, a real port or otherwise
+pub trait MidiSink : Send
+{
+	fn send(&mut self, message : &[u8]) -> Result<(),SendError>;
+}
+
+impl MidiSink for MidiOutputConnection
+{
+	fn send(&mut self, message : &[u8]) -> Result<(),SendError>
+	{
+		MidiOutputConnection::send(self,message).map_err(|e| SendError::new(format!("couldn't send {:02X?}: {}",message,e)))
+	}
+}
+
+//so a boxed sink can go anywhere a sink can
+impl<T : MidiSink + ?Sized> MidiSink for Box<T>
+{
+	fn send(&mut self, message : &[u8]) -> Result<(),SendError>
+	{
+		(**self).send(message)
+	}
+}
+
+//keeps every message in the order it went out, clones share the one log
+//so you can hand one to the player and read back what it sent from another
+#[derive(Clone,Default)]
+pub struct RecordSink
+{
+	sent : Arc<Mutex<Vec<Vec<u8>>>>
+}
+
+impl RecordSink
+{
+	pub fn new() -> RecordSink
+	{
+		RecordSink::default()
+	}
+
+	//everything since the last take, oldest first
+	pub fn take(&self) -> Vec<Vec<u8>>
+	{
+		std::mem::take(&mut *self.sent.lock().unwrap())
+	}
+}
+
+impl MidiSink for RecordSink
+{
+	fn send(&mut self, message : &[u8]) -> Result<(),SendError>
+	{
+		self.sent.lock().unwrap().push(message.to_vec());
+		Ok(())
+	}
+}
+
+//drops everything on the floor
+pub struct NullSink;
+
+impl MidiSink for NullSink
+{
+	fn send(&mut self, _message : &[u8]) -> Result<(),SendError>
+	{
+		Ok(())
+	}
+}
@@ -133,4 +133,19 @@ const NOTE_KEYS : [&'static str;128] =
 pub fn note_str(nn:u8) -> &'static str
 {
 	NOTE_KEYS[nn as usize]
-}
\ No newline at end of file
+}
+
+const PITCH_CLASSES : [&str;12] = ["C-","C#","D-","D#","E-","F-","F#","G-","G#","A-","A#","B-"];
+
+const DEGREES : [&str;12] = ["1","b2","2","b3","3","4","#4","5","b6","6","b7","7"];
+
+pub fn pitch_class_str(pc:u8) -> &'static str
+{
+	PITCH_CLASSES[(pc % 12) as usize]
+}
+
+//interval of a note above the scale root
+pub fn degree_str(nn:u8, root:u8) -> &'static str
+{
+	DEGREES[((nn as i16 - root as i16).rem_euclid(12)) as usize]
+}
@@ -1,15 +1,49 @@
 use std::result::Result;
-use midir::{MidiOutputConnection,SendError};
+use crate::midi_sink::{MidiSink,SendError};
 
 const NOTE_ON_STATUS: u8 = 0x90;
 const NOTE_OFF_STATUS: u8 = 0x80;
 
-pub fn note_on(conn: &mut MidiOutputConnection, channel: u8, nn: u8, vel: u8) -> Result<(),SendError>
+pub fn note_on(conn: &mut dyn MidiSink, channel: u8, nn: u8, vel: u8) -> Result<(),SendError>
 {
 	conn.send(&[NOTE_ON_STATUS | channel,nn,vel])
 }
 
-pub fn note_off(conn: &mut MidiOutputConnection, channel: u8, nn: u8, vel: u8) -> Result<(),SendError>
+pub fn note_off(conn: &mut dyn MidiSink, channel: u8, nn: u8, vel: u8) -> Result<(),SendError>
 {
 	conn.send(&[NOTE_OFF_STATUS | channel,nn,vel])
-}
\ No newline at end of file
+}
+
+//system real time, for anything following our clock
+const CLOCK_STATUS: u8 = 0xF8;
+const START_STATUS: u8 = 0xFA;
+const CONTINUE_STATUS: u8 = 0xFB;
+const STOP_STATUS: u8 = 0xFC;
+const SONG_POSITION_STATUS: u8 = 0xF2;
+
+pub fn clock(conn: &mut dyn MidiSink) -> Result<(),SendError>
+{
+	conn.send(&[CLOCK_STATUS])
+}
+
+pub fn start(conn: &mut dyn MidiSink) -> Result<(),SendError>
+{
+	conn.send(&[START_STATUS])
+}
+
+pub fn continue_(conn: &mut dyn MidiSink) -> Result<(),SendError>
+{
+	conn.send(&[CONTINUE_STATUS])
+}
+
+pub fn stop(conn: &mut dyn MidiSink) -> Result<(),SendError>
+{
+	conn.send(&[STOP_STATUS])
+}
+
+//in sixteenths from the top of the song
+pub fn song_position(conn: &mut dyn MidiSink, spp: usize) -> Result<(),SendError>
+{
+	let spp = std::cmp::min(spp,0x3FFF);
+	conn.send(&[SONG_POSITION_STATUS,(spp & 0x7F) as u8,(spp >> 7) as u8])
+}
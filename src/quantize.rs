@@ -0,0 +1,98 @@
+use crate::clock;
+
+use std::fmt;
+
+//where launches and stops are allowed to land, counted from when the transport started
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Quantize
+{
+	Immediate,
+	Sixteenth,
+	Beat,
+	Bar,
+	Ticks(usize) //in midi clock ticks
+}
+
+const QUANTIZES : [Quantize;4] =
+[
+	Quantize::Immediate,
+	Quantize::Sixteenth,
+	Quantize::Beat,
+	Quantize::Bar
+];
+
+impl Quantize
+{
+	pub fn parse(txt : &str) -> Option<Quantize>
+	{
+		match txt.parse::<usize>()
+		{
+			Ok(0) => None,
+			Ok(n) => Some(Quantize::Ticks(n)),
+			Err(_) => QUANTIZES.iter().find(|q| q.to_string() == txt).copied()
+		}
+	}
+
+	pub fn ticks(self) -> usize
+	{
+		match self
+		{
+			Quantize::Immediate => 1,
+			Quantize::Sixteenth => clock::PPQN / 4,
+			Quantize::Beat => clock::PPQN,
+			Quantize::Bar => clock::BAR_TICKS,
+			Quantize::Ticks(n) => n * clock::SUBTICKS
+		}
+	}
+
+	//ticks since the transport started, not just into the bar, so lengths
+	//longer than a bar or that don't fit one evenly keep their own grid
+	pub fn lands(self, ticks : usize) -> bool
+	{
+		ticks.is_multiple_of(self.ticks())
+	}
+
+	//a custom length drops back into the list at the start
+	pub fn cycle(self, forward : bool) -> Quantize
+	{
+		let next = match (QUANTIZES.iter().position(|q| *q == self),forward)
+		{
+			(None,_) => 0,
+			(Some(i),true) => (i + 1) % QUANTIZES.len(),
+			(Some(i),false) => (i + QUANTIZES.len() - 1) % QUANTIZES.len()
+		};
+
+		QUANTIZES[next]
+	}
+
+	//for per slot settings, where nothing means use the global one
+	pub fn cycle_opt(current : Option<Quantize>, forward : bool) -> Option<Quantize>
+	{
+		let idx = current.and_then(|q| QUANTIZES.iter().position(|c| *c == q));
+
+		match (idx,forward)
+		{
+			(None,true) => Some(QUANTIZES[0]),
+			(None,false) => Some(QUANTIZES[QUANTIZES.len() - 1]),
+			(Some(i),true) if i + 1 == QUANTIZES.len() => None,
+			(Some(0),false) => None,
+			(Some(i),true) => Some(QUANTIZES[i + 1]),
+			(Some(i),false) => Some(QUANTIZES[i - 1])
+		}
+	}
+}
+
+impl fmt::Display for Quantize
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Quantize::Immediate => write!(f,"immediate"),
+			Quantize::Sixteenth => write!(f,"16th"),
+			Quantize::Beat => write!(f,"beat"),
+			Quantize::Bar => write!(f,"bar"),
+			Quantize::Ticks(n) => write!(f,"{}",n)
+		}
+	}
+}
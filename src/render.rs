@@ -0,0 +1,231 @@
+use crate::clock;
+use crate::config_file;
+use crate::midi_sink::{MidiSink,RecordSink};
+use crate::sequence_player::Player;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+const USAGE:&str = "usage: curse_screen render <out.mid> <bars> <slot>[,<slot>...] [config]";
+
+//everything one port sent on one channel, stamped with the tick it went out on,
+//keyed by the port number from the config
+type Tracks = BTreeMap<(usize,u8),Vec<(usize,Vec<u8>)>>;
+
+pub fn cli(args : &[String]) -> Result<(),Box<dyn Error>>
+{
+	let (out_path,bars,slots) = match args
+	{
+		[out_path,bars,slots,..] => (out_path,bars,slots),
+		_ => return Err(USAGE.into())
+	};
+
+	let bars : usize = bars.parse().map_err(|_| format!("bars should be a number, not {}\n{}",bars,USAGE))?;
+
+	let slots = slots.split(',')
+	.map(|s| match s.trim().parse::<usize>()
+	{
+		Ok(n) if n < 128 => Ok(n),
+		_ => Err(format!("slots are 0 to 127, not {}\n{}",s,USAGE))
+	})
+	.collect::<Result<Vec<usize>,String>>()?;
+
+	let conf_path = args.get(3).map(|s| s.as_str()).unwrap_or("./conf.json");
+
+	render(conf_path,out_path,bars,&slots)
+}
+
+//runs the player flat out instead of in time, the slots are launched as if their keys
+//went down before the first tick and are held for the whole thing
+pub fn render(conf_path : &str, out_path : &str, bars : usize, slots : &[usize]) -> Result<(),Box<dyn Error>>
+{
+	let conf = config_file::read_config(conf_path)?;
+
+	let mut player = Player::blank();
+	let mut sinks : Vec<(usize,RecordSink)> = vec![];
+
+	config_file::apply_config(&mut player,&conf,&mut |port_num|
+	{
+		let sink = RecordSink::new();
+		sinks.push((port_num,sink.clone()));
+		Ok(Box::new(sink) as Box<dyn MidiSink>)
+	})?;
+
+	for slot in slots
+	{
+		player.note_on(*slot);
+	}
+
+	let length = bars * clock::BAR_TICKS;
+	let tracks = play(&mut player,&sinks,length);
+
+	let mut file = File::create(out_path)?;
+	//the tempo the player actually ran at, after it's been clamped
+	file.write_all(&smf(player.tempo.get(),length,&tracks))?;
+
+	Ok(())
+}
+
+fn play(player : &mut Player, sinks : &[(usize,RecordSink)], length : usize) -> Tracks
+{
+	let mut tracks = Tracks::new();
+
+	for tick in 0..length
+	{
+		player.tick();
+		collect(sinks,tick,&mut tracks);
+	}
+
+	//let anything still sounding go at the very end
+	player.transport_stop();
+	collect(sinks,length,&mut tracks);
+
+	tracks
+}
+
+//clock and transport are for live followers, only channel messages go in the file
+fn collect(sinks : &[(usize,RecordSink)], tick : usize, tracks : &mut Tracks)
+{
+	for (port,sink) in sinks.iter()
+	{
+		for message in sink.take()
+		{
+			match message.first()
+			{
+				Some(status) if (0x80..0xF0).contains(status) =>
+				{
+					tracks.entry((*port,status & 0x0F)).or_default().push((tick,message));
+				}
+
+				_ => ()
+			}
+		}
+	}
+}
+
+//=============================================================================
+// STANDARD MIDI FILE
+//=============================================================================
+fn push_vlq(out : &mut Vec<u8>, n : usize)
+{
+	let mut bytes = vec![(n & 0x7F) as u8];
+	let mut n = n >> 7;
+
+	while n > 0
+	{
+		bytes.push((n & 0x7F) as u8 | 0x80);
+		n >>= 7;
+	}
+
+	bytes.reverse();
+	out.extend(bytes);
+}
+
+fn push_meta(out : &mut Vec<u8>, delta : usize, kind : u8, data : &[u8])
+{
+	push_vlq(out,delta);
+	out.extend([0xFF,kind]);
+	push_vlq(out,data.len());
+	out.extend(data);
+}
+
+fn push_chunk(out : &mut Vec<u8>, id : &[u8], body : &[u8])
+{
+	out.extend(id);
+	out.extend((body.len() as u32).to_be_bytes());
+	out.extend(body);
+}
+
+//type 1, a tempo track first then one track for each port and channel,
+//every track ends on the last tick so they all line up in whatever loads it
+fn smf(bpm : f64, length : usize, tracks : &Tracks) -> Vec<u8>
+{
+	let mut out = vec![];
+
+	let mut header = vec![];
+	header.extend(1u16.to_be_bytes());
+	header.extend(((tracks.len() + 1) as u16).to_be_bytes());
+	header.extend((clock::PPQN as u16).to_be_bytes());
+	push_chunk(&mut out,b"MThd",&header);
+
+	let mut tempo = vec![];
+	let usecs = (60_000_000.0 / bpm).round() as u32;
+	push_meta(&mut tempo,0,0x51,&usecs.to_be_bytes()[1..]);
+	push_meta(&mut tempo,0,0x58,&[clock::BEATS_PER_BAR as u8,2,24,8]);
+	push_meta(&mut tempo,length,0x2F,&[]);
+	push_chunk(&mut out,b"MTrk",&tempo);
+
+	for ((port,channel),events) in tracks.iter()
+	{
+		let mut track = vec![];
+		push_meta(&mut track,0,0x03,format!("port {} ch {}",port,channel + 1).as_bytes());
+
+		let mut last = 0;
+
+		for (tick,message) in events.iter()
+		{
+			push_vlq(&mut track,tick - last);
+			track.extend(message);
+			last = *tick;
+		}
+
+		push_meta(&mut track,length - last,0x2F,&[]);
+		push_chunk(&mut out,b"MTrk",&track);
+	}
+
+	out
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn vlq(n : usize) -> Vec<u8>
+	{
+		let mut out = vec![];
+		push_vlq(&mut out,n);
+		out
+	}
+
+	#[test]
+	fn lengths_go_out_seven_bits_at_a_time()
+	{
+		assert_eq!(vlq(0),[0x00]);
+		assert_eq!(vlq(0x7F),[0x7F]);
+		assert_eq!(vlq(0x80),[0x81,0x00]);
+		assert_eq!(vlq(0x3FFF),[0xFF,0x7F]);
+		assert_eq!(vlq(0x200000),[0x81,0x80,0x80,0x00]);
+	}
+
+	#[test]
+	fn tracks_are_named_for_the_configured_port()
+	{
+		let sink = RecordSink::new();
+		let mut player = Player::blank();
+		player.out_connections.push((3,Box::new(sink.clone())));
+
+		let seq = &mut player.midi_map[0];
+		seq.channel = 9;
+		seq.patterns[0].length = 1;
+		seq.patterns[0].steps[0].add_note(36,100);
+
+		player.note_on(0);
+
+		let tracks = play(&mut player,&[(3,sink)],clock::BAR_TICKS);
+		assert_eq!(tracks.keys().collect::<Vec<_>>(),[&(3,9)]);
+
+		//a note on the first tick and again on the next step, a sixteenth later
+		let events = &tracks[&(3,9)];
+		assert_eq!(events[0],(0,vec![0x99,36,100]));
+		assert!(events.iter().any(|(tick,m)| *tick == clock::PPQN / 4 && m[0] == 0x99));
+
+		let file = smf(120.0,clock::BAR_TICKS,&tracks);
+		assert_eq!(&file[0..4],b"MThd");
+		//the tempo track and the one for port 3
+		assert_eq!(&file[10..12],&2u16.to_be_bytes());
+		assert!(file.windows(12).any(|w| w == b"port 3 ch 10"));
+	}
+}
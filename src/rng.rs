@@ -0,0 +1,45 @@
+//small seedable generator so a saved seed plays back the same way every time
+#[derive(Debug,Copy,Clone)]
+pub struct Rng
+{
+	state: u64
+}
+
+impl Rng
+{
+	pub fn new(seed : u64) -> Rng
+	{
+		//run the seed through splitmix so 0 and nearby seeds are still usable
+		let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^= z >> 31;
+
+		Rng{state: if z == 0 { 1 } else { z }}
+	}
+
+	pub fn next_u64(&mut self) -> u64
+	{
+		//xorshift64*
+		self.state ^= self.state >> 12;
+		self.state ^= self.state << 25;
+		self.state ^= self.state >> 27;
+		self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+	}
+
+	pub fn below(&mut self, n : usize) -> usize
+	{
+		if n == 0
+		{
+			return 0
+		}
+
+		((self.next_u64() >> 32) % n as u64) as usize
+	}
+
+	//true pct times out of 100
+	pub fn chance(&mut self, pct : u8) -> bool
+	{
+		self.below(100) < pct as usize
+	}
+}
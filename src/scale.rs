@@ -0,0 +1,167 @@
+//scales are a 12 bit mask of which semitones above the root are in key
+pub const CHROMATIC:u16 = 0xFFF;
+
+pub const SCALES : [(&str,u16);11] =
+[
+	("major",          0b1010_1011_0101),
+	("minor",          0b0101_1010_1101),
+	("dorian",         0b0110_1010_1101),
+	("phrygian",       0b0101_1010_1011),
+	("lydian",         0b1010_1101_0101),
+	("mixolydian",     0b0110_1011_0101),
+	("locrian",        0b0101_0110_1011),
+	("major_pent",     0b0010_1001_0101),
+	("minor_pent",     0b0100_1010_1001),
+	("harmonic_minor", 0b1001_1010_1101),
+	("chromatic",      CHROMATIC)
+];
+
+fn in_scale(nn : i16, root : u8, mask : u16) -> bool
+{
+	let degree = (nn - root as i16).rem_euclid(12);
+	mask & (1 << degree) != 0
+}
+
+//snaps to the nearest note in the scale, a tie goes down
+pub fn quantize(nn : u8, root : u8, mask : u16) -> u8
+{
+	if mask & CHROMATIC == 0
+	{
+		return nn
+	}
+
+	for dist in 0..12
+	{
+		let down = nn as i16 - dist;
+		let up = nn as i16 + dist;
+
+		if down >= 0 && in_scale(down,root,mask)
+		{
+			return down as u8
+		}
+
+		if up <= 0x7F && in_scale(up,root,mask)
+		{
+			return up as u8
+		}
+	}
+
+	nn
+}
+
+//either one of the names above or 12 ones and zeros starting from the root
+pub fn parse(txt : &str) -> Option<u16>
+{
+	if let Some((_,mask)) = SCALES.iter().find(|(name,_)| *name == txt)
+	{
+		return Some(*mask)
+	}
+
+	if txt.len() != 12
+	{
+		return None
+	}
+
+	let mut mask = 0;
+
+	for (i,c) in txt.chars().enumerate()
+	{
+		match c
+		{
+			'1' => mask |= 1 << i,
+			'0' => (),
+			_ => return None
+		}
+	}
+
+	Some(mask)
+}
+
+pub fn name(mask : u16) -> String
+{
+	match SCALES.iter().find(|(_,m)| *m == mask)
+	{
+		Some((name,_)) => name.to_string(),
+		None => (0..12).map(|i| if mask & (1 << i) != 0 { '1' } else { '0' }).collect()
+	}
+}
+
+//steps through the named scales, off sits at either end
+pub fn cycle(current : Option<u16>, forward : bool) -> Option<u16>
+{
+	let idx = current.and_then(|mask| SCALES.iter().position(|(_,m)| *m == mask));
+
+	match (idx,forward)
+	{
+		(None,true) => Some(SCALES[0].1),
+		(None,false) => Some(SCALES[SCALES.len() - 1].1),
+		(Some(i),true) if i + 1 == SCALES.len() => None,
+		(Some(0),false) => None,
+		(Some(i),true) => Some(SCALES[i + 1].1),
+		(Some(i),false) => Some(SCALES[i - 1].1)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const MAJOR:u16 = SCALES[0].1;
+	const MINOR_PENT:u16 = SCALES[8].1;
+
+	#[test]
+	fn notes_in_key_are_left_alone()
+	{
+		for nn in [60,62,64,65,67,69,71,72]
+		{
+			assert_eq!(quantize(nn,0,MAJOR),nn);
+		}
+	}
+
+	#[test]
+	fn notes_out_of_key_snap_to_the_nearest_and_ties_go_down()
+	{
+		assert_eq!(quantize(61,0,MAJOR),60);
+		assert_eq!(quantize(66,0,MAJOR),65);
+
+		//a minor pentatonic has gaps wider than a tone, B is a semitone from C but a tone from A
+		assert_eq!(quantize(59,9,MINOR_PENT),60);
+		assert_eq!(quantize(58,9,MINOR_PENT),57);
+	}
+
+	#[test]
+	fn snapping_never_goes_off_the_top()
+	{
+		//G is out of C# major and G# would be past 127
+		assert_eq!(quantize(127,1,MAJOR),126);
+	}
+
+	#[test]
+	fn an_empty_mask_passes_everything()
+	{
+		assert_eq!(quantize(61,0,0),61);
+	}
+
+	#[test]
+	fn names_and_bit_strings_both_parse()
+	{
+		assert_eq!(parse("major"),Some(MAJOR));
+		assert_eq!(parse("101011010101"),Some(MAJOR));
+		assert_eq!(parse("10101101010"),None);
+		assert_eq!(parse("10101101010x"),None);
+		assert_eq!(parse("nonsense"),None);
+
+		assert_eq!(name(MAJOR),"major");
+		assert_eq!(name(0b1001),"100100000000");
+	}
+
+	#[test]
+	fn cycling_passes_through_off_at_either_end()
+	{
+		assert_eq!(cycle(None,true),Some(MAJOR));
+		assert_eq!(cycle(Some(CHROMATIC),true),None);
+		assert_eq!(cycle(Some(MAJOR),false),None);
+		assert_eq!(cycle(None,false),Some(CHROMATIC));
+	}
+}
@@ -0,0 +1,20 @@
+//a snapshot of which slots were running and whether they were latched
+#[derive(Debug,Clone)]
+pub struct Scene
+{
+	pub name: String,
+	pub slots: Vec<(usize,bool)> //slot and its hold
+}
+
+impl Scene
+{
+	pub fn new(name : String) -> Scene
+	{
+		Scene{name,slots:vec![]}
+	}
+
+	pub fn has_slot(&self, slot : usize) -> bool
+	{
+		self.slots.iter().any(|(s,_)| *s == slot)
+	}
+}
@@ -0,0 +1,167 @@
+use crate::input_types::Input;
+use crate::tempo::{self,Tempo};
+
+use std::fmt;
+use std::sync::{atomic::{Ordering,AtomicBool},mpsc::Sender,Arc};
+use std::thread::{self,JoinHandle};
+use std::time::{Duration,Instant};
+
+//sleeping gets us most of the way, the last bit is spun so the wake up is tight,
+//how much gets spun follows how late the sleeps have actually been waking up
+const SPIN_MIN:Duration = Duration::from_micros(200);
+const SPIN_MAX:Duration = Duration::from_micros(1500);
+
+//further behind than this and we give up catching up and start counting again from now
+const MAX_BEHIND:Duration = Duration::from_millis(100);
+
+//anything later than this counts as a miss in the report
+const LATE:Duration = Duration::from_millis(1);
+
+//how late each tick went out against its deadline
+#[derive(Debug,Default,Copy,Clone)]
+pub struct Jitter
+{
+	count: u64,
+	total: Duration,
+	worst: Duration,
+	late: u64,
+
+	//time spent spinning out of the time running, for what keeping time costs
+	spun: Duration,
+	running: Duration
+}
+
+impl Jitter
+{
+	pub fn record(&mut self, lateness : Duration)
+	{
+		self.count += 1;
+		self.total += lateness;
+		self.worst = std::cmp::max(self.worst,lateness);
+
+		if lateness > LATE
+		{
+			self.late += 1;
+		}
+	}
+
+	pub fn record_spin(&mut self, spun : Duration, running : Duration)
+	{
+		self.spun = spun;
+		self.running = running;
+	}
+}
+
+impl fmt::Display for Jitter
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		let mean = if self.count > 0 { self.total.as_secs_f64() / self.count as f64 } else { 0.0 };
+
+		write!(f,"{} ticks, mean {:.3}ms, worst {:.3}ms, {} over {}ms",self.count,mean * 1000.0,self.worst.as_secs_f64() * 1000.0,self.late,LATE.as_millis())?;
+
+		if !self.running.is_zero()
+		{
+			write!(f,", spinning {:.1}% of a core",100.0 * self.spun.as_secs_f64() / self.running.as_secs_f64())?;
+		}
+
+		Ok(())
+	}
+}
+
+struct Waiter
+{
+	margin: Duration,
+	spun: Duration
+}
+
+impl Waiter
+{
+	fn new() -> Waiter
+	{
+		Waiter{margin: SPIN_MAX,spun: Duration::ZERO}
+	}
+
+	//the margin jumps up as soon as a sleep runs long and only creeps back down,
+	//with twice the oversleep as headroom
+	fn wait_until(&mut self, deadline : Instant)
+	{
+		let now = Instant::now();
+
+		if deadline > now + self.margin
+		{
+			let wake = deadline - self.margin;
+			thread::sleep(wake - now);
+
+			let want = Instant::now().saturating_duration_since(wake) * 2;
+
+			self.margin = if want > self.margin
+			{
+				want
+			}
+			else 
+			{
+				self.margin - (self.margin - want) / 64
+			}.clamp(SPIN_MIN,SPIN_MAX);
+		}
+
+		let spin_start = Instant::now();
+
+		while Instant::now() < deadline
+		{
+			std::hint::spin_loop();
+		}
+
+		self.spun += spin_start.elapsed();
+	}
+}
+
+//deadlines are worked out from a fixed origin rather than added up tick by tick,
+//so rounding never builds up, a tempo change just starts a new origin at the last deadline
+pub fn spawn(tempo : Arc<Tempo>, hangup : Arc<AtomicBool>, tx : Sender<Input>) -> JoinHandle<Jitter>
+{
+	thread::spawn(move ||
+	{
+		let mut jitter = Jitter::default();
+		let mut waiter = Waiter::new();
+		let started = Instant::now();
+
+		let mut bpm = tempo.get();
+		let mut origin = Instant::now();
+		let mut ticks : u64 = 0;
+		let mut deadline = origin;
+
+		while !hangup.load(Ordering::Relaxed)
+		{
+			if tempo.get() != bpm
+			{
+				bpm = tempo.get();
+				origin = deadline;
+				ticks = 0;
+			}
+
+			ticks += 1;
+			deadline = origin + Duration::from_secs_f64(tempo::tick_secs(bpm) * ticks as f64);
+
+			waiter.wait_until(deadline);
+
+			let now = Instant::now();
+			jitter.record(now - deadline);
+
+			if tx.send(Input::Tick(deadline)).is_err()
+			{
+				break;
+			}
+
+			if now - deadline > MAX_BEHIND
+			{
+				origin = now;
+				ticks = 0;
+				deadline = now;
+			}
+		}
+
+		jitter.record_spin(waiter.spun,started.elapsed());
+		jitter
+	})
+}
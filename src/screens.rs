@@ -1,8 +1,12 @@
-use crate::sequence::{self,Seq,Trigger};
+use crate::sequence;
 use crate::note_lookup;
+use crate::scale;
+use crate::song::SongState;
+use crate::tempo;
 use crate::input_types::Input;
 use crate::sequence_player::Player;
 use crate::midi_msg::MidiMessage;
+use crate::engine::{Snapshot,EditView};
 
 
 use termion::event::{Event,Key};
@@ -10,14 +14,57 @@ use termion::{clear,color,cursor};
 use termion::color::*;
 
 use std::io::{Write,stdout, stdin};
+use std::sync::atomic::Ordering;
 
 const BAR_SIZE:usize = 16;
 
-fn draw_seq(mut screen : impl std::io::Write, x : u16,y : u16,seq : &Seq)
+const SCENE_KEYS:&str = "1234567890";
+const SCENE_STORE_KEYS:&str = "!@#$%^&*()";
+
+fn group_str(choke_group : Option<u8>,mute_group : Option<u8>,muted : bool,soloed : bool) -> String
+{
+	let mut groups = String::new();
+
+	if let Some(g) = choke_group
+	{
+		groups.push_str(&format!("    choke:{}",g));
+	}
+
+	if let Some(g) = mute_group
+	{
+		groups.push_str(&format!("    mute:{}",g));
+	}
+
+	if muted
+	{
+		groups.push_str("    MUTE");
+	}
+
+	if soloed
+	{
+		groups.push_str("    SOLO");
+	}
+
+	groups
+}
+
+//quiet notes fade towards black, full velocity is the old plain red
+fn vel_shade(vel : u8) -> Rgb
+{
+	let level = 0x40 + (vel as u16 * 0xBF / 0x7F) as u8;
+	Rgb(level,0,0)
+}
+
+fn draw_seq(mut screen : impl std::io::Write, x : u16,y : u16,seq : &EditView,snap : bool)
 {
 	write!(screen,"{}{}\n",cursor::Goto(x,y),clear::CurrentLine).unwrap();
 
-	for i in 0..seq.length
+	let pattern = &seq.editing;
+
+	//the playhead only shows when the pattern being edited is the one playing
+	let position = if seq.edit_pattern == seq.pattern { Some(seq.position) } else { None };
+
+	for i in 0..pattern.length
 	{
 		if i > 0 && i % BAR_SIZE == 0
 		{
@@ -28,7 +75,7 @@ fn draw_seq(mut screen : impl std::io::Write, x : u16,y : u16,seq : &Seq)
 			};
 		}
 
-		let step = seq.steps[i];
+		let step = pattern.steps[i];
 
 		match (step.hold,i == seq.edit_step)
 		{
@@ -37,13 +84,22 @@ fn draw_seq(mut screen : impl std::io::Write, x : u16,y : u16,seq : &Seq)
 			(false,true) => write!(screen,"{}{}{}X{}{}{}",cursor::Right(1),cursor::Up(1),Fg(Red),Fg(Reset),cursor::Down(1),cursor::Left(2)).unwrap(),
 			(_,_) => ()
 		}
+
+		//the ratchet count sits above the gap after the note
+		if step.ratchet > 1
+		{
+			write!(screen,"{}{}{}{}{}{}{}",cursor::Up(1),cursor::Right(3),Fg(Yellow),step.ratchet,Fg(Reset),cursor::Down(1),cursor::Left(4)).unwrap();
+		}
 		
-		match (step.trig,i==seq.position)
+		//chords show their first note with a + after it, steps that might not play get a ?
+		let chord = if step.is_chancy() { "?" } else if step.note_count() > 1 { "+" } else { " " };
+
+		match (step.first_note(),Some(i)==position)
 		{
-			(Trigger::Off,false) => write!(screen,"{}---{} ",color::Fg(Green),color::Fg(Reset)),
-			(Trigger::On(note,_),false) => write!(screen,"{}{}{} ",color::Fg(Red),note_lookup::note_str(note),color::Fg(Reset)),
-			(Trigger::Off,true) => write!(screen,"{}---{} ",color::Fg(Magenta),color::Fg(Reset)),
-			(Trigger::On(note,_),true) => write!(screen,"{}{}{} ",color::Fg(Magenta),note_lookup::note_str(note),color::Fg(Reset)),
+			(None,false) => write!(screen,"{}---{} ",color::Fg(Green),color::Fg(Reset)),
+			(Some((note,vel)),false) => write!(screen,"{}{}{}{}",color::Fg(vel_shade(vel)),note_lookup::note_str(note),color::Fg(Reset),chord),
+			(None,true) => write!(screen,"{}---{} ",color::Fg(Magenta),color::Fg(Reset)),
+			(Some((note,_)),true) => write!(screen,"{}{}{}{}",color::Fg(Magenta),note_lookup::note_str(note),color::Fg(Reset),chord),
 		}.unwrap();
 	}
 
@@ -52,25 +108,124 @@ fn draw_seq(mut screen : impl std::io::Write, x : u16,y : u16,seq : &Seq)
 
 	//ok now draw the ticks per step
 	write!(screen,"ticks: {}    channel:{}    port:{}",seq.ticks_per_step,seq.channel,seq.port).unwrap();
+
+	match seq.global_swing
+	{
+		Some(s) => write!(screen,"    swing:{}% (global)",s).unwrap(),
+		None => write!(screen,"    swing:{}%",seq.swing).unwrap()
+	}
+
+	write!(screen,"    dir:{}",seq.direction).unwrap();
+
+	if let Some(q) = seq.launch_quantize
+	{
+		write!(screen,"    launch:{}",q).unwrap();
+	}
+
+	if let Some(q) = seq.stop_quantize
+	{
+		write!(screen,"    stop:{}",q).unwrap();
+	}
+
+	write!(screen,"{}",group_str(seq.choke_group,seq.mute_group,seq.muted,seq.soloed)).unwrap();
+
+	write!(screen,"    pattern:{}/{} (playing {})",seq.edit_pattern + 1,sequence::BANK_SIZE,seq.pattern + 1).unwrap();
+
+	if let Some(q) = seq.queued
+	{
+		write!(screen," -> {}",q + 1).unwrap();
+	}
+
+	//pitch handling gets its own line
+	write!(screen,"\r\n{}",cursor::Right(x - 1)).unwrap();
+
+	if let Some(mask) = seq.scale
+	{
+		let snap = if snap { " (snap)" } else { "" };
+		write!(screen,"scale:{} {}{}    ",note_lookup::pitch_class_str(seq.scale_root),scale::name(mask),snap).unwrap();
+	}
+
+	match (seq.key_range,seq.arp)
+	{
+		(Some((lo,hi)),Some(arp)) =>
+		{
+			write!(screen,"keys:{}..{}  arp:{} x{}  held:",note_lookup::note_str(lo),note_lookup::note_str(hi),arp.pattern,arp.octaves).unwrap();
+
+			for nn in arp.notes()
+			{
+				write!(screen," {}",note_lookup::note_str(*nn)).unwrap();
+			}
+		}
+
+		(Some((lo,hi)),None) => write!(screen,"keys:{}..{}  root:{}  transpose:{:+}",note_lookup::note_str(lo),note_lookup::note_str(hi),note_lookup::note_str(seq.root),seq.transpose).unwrap(),
+
+		_ => ()
+	}
+
+	//and then whatever is on the step being edited
+	write!(screen,"\r\n{}",cursor::Right(x - 1)).unwrap();
+
+	let edit_step = pattern.steps[seq.edit_step];
+
+	if let Some((nn,vel)) = edit_step.first_note()
+	{
+		write!(screen,"vel:{}    gate:{}%    prob:{}%    cond:{}    ratchet:x{}",vel,edit_step.gate,edit_step.prob,edit_step.cond,edit_step.ratchet).unwrap();
+
+		//the degree of what actually goes out, once it's been pulled into key
+		if let Some(mask) = seq.scale
+		{
+			write!(screen,"    deg:{}",note_lookup::degree_str(scale::quantize(nn,seq.scale_root,mask),seq.scale_root)).unwrap();
+		}
+	}
+
+	if edit_step.nudge != 0
+	{
+		write!(screen,"    nudge:{:+}",edit_step.nudge).unwrap();
+	}
+
+	if edit_step.note_count() > 1
+	{
+		write!(screen,"\r\n{}chord:",cursor::Right(x - 1)).unwrap();
+
+		for (nn,_) in edit_step.notes()
+		{
+			write!(screen," {}",note_lookup::note_str(nn)).unwrap();
+		}
+	}
+
 	write!(screen,"\r\n{}",clear::AfterCursor).unwrap();
 }
 
+#[derive(Clone)]
 enum Mode
 {
 	Edit,
-	Play
+	Play,
+	Song
 }
 
+#[derive(Clone)]
 struct EditState
 {
-	current_edit : usize
+	current_edit : usize,
+	held_keys : usize, //notes pressed together build up a chord on one step
+	snap : bool //pull played notes into the sequence's scale as they're entered
 }
 
+#[derive(Clone)]
 struct PlayState
 {
 
 }
 
+#[derive(Clone)]
+struct SongScreenState
+{
+	selected : usize
+}
+
+//lives with the player on the engine thread, the ui draws from a copy of it
+#[derive(Clone)]
 pub struct Screen
 {
 	w : u16,
@@ -78,7 +233,8 @@ pub struct Screen
 
 	mode : Mode,
 	edit_state: EditState,
-	play_state : PlayState
+	play_state : PlayState,
+	song_state : SongScreenState
 }
 
 impl Screen
@@ -92,33 +248,54 @@ impl Screen
 			w: sw,
 			h: sh,
 			mode : Mode::Play,
-			edit_state: EditState{current_edit:0},
-			play_state : PlayState{}
+			edit_state: EditState{current_edit:0,held_keys:0,snap:false},
+			play_state : PlayState{},
+			song_state : SongScreenState{selected:0}
 		}
 	}
 
+	pub fn current_edit(&self) -> usize
+	{
+		self.edit_state.current_edit
+	}
+
 	fn edit_midi_input(&mut self,player: &mut Player, evt:MidiMessage ) -> bool
 	{
 		let edit_seq = & mut player.midi_map[self.edit_state.current_edit];
 
 		match evt
 		{
-			MidiMessage::NoteOn(_,nn,0)  =>
+			MidiMessage::NoteOn(_,nn,0) | MidiMessage::NoteOff(_,nn,_) =>
 			{
+				//the step is done once the whole chord has been let go
+				if self.edit_state.held_keys > 0
+				{
+					self.edit_state.held_keys -= 1;
+
+					if self.edit_state.held_keys == 0
+					{
+						edit_seq.edit_step_up_wrap();
+					}
+				}
+
 				player.note_off(nn as usize);
 				true
 			},
 
 			MidiMessage::NoteOn(_,nn,vel)  =>
 			{
-				edit_seq.set_step_note(nn,0x7F);
-				edit_seq.edit_step_up_wrap();
-				true
-			},
+				let nn = if self.edit_state.snap { edit_seq.quantize(nn) } else { nn };
 
-			MidiMessage::NoteOff(c,nn,v) =>
-			{
-				player.note_off(nn as usize);
+				if self.edit_state.held_keys == 0
+				{
+					edit_seq.set_step_note(nn,vel);
+				}
+				else 
+				{
+					edit_seq.add_step_note(nn,vel);
+				}
+
+				self.edit_state.held_keys += 1;
 				true
 			},
 
@@ -169,6 +346,242 @@ impl Screen
 				true
 			}
 
+			Input::Keyboard(Key::Char('v')) =>
+			{
+				edit_seq.down_step_vel();
+				true
+			}
+
+			Input::Keyboard(Key::Char('V')) =>
+			{
+				edit_seq.up_step_vel();
+				true
+			}
+
+			Input::Keyboard(Key::Char('g')) =>
+			{
+				edit_seq.down_step_gate();
+				true
+			}
+
+			Input::Keyboard(Key::Char('G')) =>
+			{
+				edit_seq.up_step_gate();
+				true
+			}
+
+			Input::Keyboard(Key::Char('p')) =>
+			{
+				edit_seq.down_step_prob();
+				true
+			}
+
+			Input::Keyboard(Key::Char('P')) =>
+			{
+				edit_seq.up_step_prob();
+				true
+			}
+
+			Input::Keyboard(Key::Char('c')) =>
+			{
+				edit_seq.next_step_cond();
+				true
+			}
+
+			Input::Keyboard(Key::Char('C')) =>
+			{
+				edit_seq.prev_step_cond();
+				true
+			}
+
+			Input::Keyboard(Key::Char('r')) =>
+			{
+				edit_seq.down_step_ratchet();
+				true
+			}
+
+			Input::Keyboard(Key::Char('R')) =>
+			{
+				edit_seq.up_step_ratchet();
+				true
+			}
+
+			Input::Keyboard(Key::Char('n')) =>
+			{
+				edit_seq.step_earlier();
+				true
+			}
+
+			Input::Keyboard(Key::Char('N')) =>
+			{
+				edit_seq.step_later();
+				true
+			}
+
+			Input::Keyboard(Key::Char('s')) =>
+			{
+				edit_seq.down_swing();
+				true
+			}
+
+			Input::Keyboard(Key::Char('S')) =>
+			{
+				edit_seq.up_swing();
+				true
+			}
+
+			Input::Keyboard(Key::Char('d')) =>
+			{
+				edit_seq.next_direction();
+				true
+			}
+
+			Input::Keyboard(Key::Char('D')) =>
+			{
+				edit_seq.prev_direction();
+				true
+			}
+
+			Input::Keyboard(Key::Char('l')) =>
+			{
+				edit_seq.next_launch_quantize();
+				true
+			}
+
+			Input::Keyboard(Key::Char('L')) =>
+			{
+				edit_seq.prev_launch_quantize();
+				true
+			}
+
+			Input::Keyboard(Key::Char('e')) =>
+			{
+				edit_seq.next_stop_quantize();
+				true
+			}
+
+			Input::Keyboard(Key::Char('E')) =>
+			{
+				edit_seq.prev_stop_quantize();
+				true
+			}
+
+			Input::Keyboard(Key::Char('j')) =>
+			{
+				edit_seq.down_choke_group();
+				true
+			}
+
+			Input::Keyboard(Key::Char('J')) =>
+			{
+				edit_seq.up_choke_group();
+				true
+			}
+
+			Input::Keyboard(Key::Char('u')) =>
+			{
+				edit_seq.down_mute_group();
+				true
+			}
+
+			Input::Keyboard(Key::Char('U')) =>
+			{
+				edit_seq.up_mute_group();
+				true
+			}
+
+			Input::Keyboard(Key::Char('t')) =>
+			{
+				edit_seq.toggle_key_range(self.edit_state.current_edit as u8);
+				true
+			}
+
+			Input::Keyboard(Key::Char('k')) =>
+			{
+				edit_seq.narrow_key_range();
+				true
+			}
+
+			Input::Keyboard(Key::Char('K')) =>
+			{
+				edit_seq.widen_key_range();
+				true
+			}
+
+			Input::Keyboard(Key::Char('q')) =>
+			{
+				edit_seq.next_scale();
+				true
+			}
+
+			//Q is quit, so stepping back through the scales gets its own key
+			Input::Keyboard(Key::Char('y')) =>
+			{
+				edit_seq.prev_scale();
+				true
+			}
+
+			Input::Keyboard(Key::Char('w')) =>
+			{
+				edit_seq.down_scale_root();
+				true
+			}
+
+			Input::Keyboard(Key::Char('W')) =>
+			{
+				edit_seq.up_scale_root();
+				true
+			}
+
+			Input::Keyboard(Key::Char('z')) =>
+			{
+				self.edit_state.snap = !self.edit_state.snap;
+				true
+			}
+
+			Input::Keyboard(Key::Char('a')) =>
+			{
+				edit_seq.toggle_arp(self.edit_state.current_edit as u8);
+				true
+			}
+
+			Input::Keyboard(Key::Char('A')) =>
+			{
+				edit_seq.next_arp_pattern();
+				true
+			}
+
+			Input::Keyboard(Key::Char('o')) =>
+			{
+				edit_seq.down_arp_octaves();
+				true
+			}
+
+			Input::Keyboard(Key::Char('O')) =>
+			{
+				edit_seq.up_arp_octaves();
+				true
+			}
+
+			Input::Keyboard(Key::Char(',')) =>
+			{
+				edit_seq.prev_edit_pattern();
+				true
+			}
+
+			Input::Keyboard(Key::Char('.')) =>
+			{
+				edit_seq.next_edit_pattern();
+				true
+			}
+
+			Input::Keyboard(Key::Char('\n')) =>
+			{
+				let pattern = edit_seq.edit_pattern;
+				edit_seq.queue_pattern(pattern);
+				true
+			}
+
 			Input::Keyboard(Key::Char('-')) =>
 			{
 				edit_seq.drop_step();
@@ -230,6 +643,15 @@ impl Screen
 	{
 		match evt
 		{
+			//scene notes only ever recall scenes, letting go of them does nothing
+			Input::Midi(MidiMessage::NoteOn(_,nn,0)) | Input::Midi(MidiMessage::NoteOff(_,nn,_)) if player.scene_note(nn).is_some() => false,
+
+			Input::Midi(MidiMessage::NoteOn(_,nn,_)) if player.scene_note(nn).is_some() =>
+			{
+				player.recall_scene(player.scene_note(nn).unwrap_or(0));
+				true
+			},
+
 			Input::Midi(MidiMessage::NoteOn(c,nn,0)) =>
 			{
 				player.note_off(nn as usize);
@@ -238,8 +660,7 @@ impl Screen
 
 			Input::Midi(MidiMessage::NoteOn(c,nn,v)) =>
 			{
-				player.note_on(nn as usize);
-				self.edit_state.current_edit = nn as usize;
+				self.edit_state.current_edit = player.note_on(nn as usize);
 				true
 			},
 
@@ -255,6 +676,223 @@ impl Screen
 				true
 			}
 
+			Input::Keyboard(Key::Char('f')) =>
+			{
+				player.toggle_fill();
+				true
+			}
+
+			Input::Keyboard(Key::Char(',')) =>
+			{
+				let seq = &mut player.midi_map[self.edit_state.current_edit];
+				let pattern = seq.queued.unwrap_or(seq.pattern);
+				seq.queue_pattern((pattern + sequence::BANK_SIZE - 1) % sequence::BANK_SIZE);
+				true
+			}
+
+			Input::Keyboard(Key::Char('.')) =>
+			{
+				let seq = &mut player.midi_map[self.edit_state.current_edit];
+				let pattern = seq.queued.unwrap_or(seq.pattern);
+				seq.queue_pattern((pattern + 1) % sequence::BANK_SIZE);
+				true
+			}
+
+			Input::Keyboard(Key::Char('s')) =>
+			{
+				player.down_swing();
+				true
+			}
+
+			//number keys recall scenes, shifted they store them
+			Input::Keyboard(Key::Char(c)) if SCENE_KEYS.contains(c) =>
+			{
+				player.recall_scene(SCENE_KEYS.find(c).unwrap_or(0));
+				true
+			}
+
+			Input::Keyboard(Key::Char(c)) if SCENE_STORE_KEYS.contains(c) =>
+			{
+				player.store_scene(SCENE_STORE_KEYS.find(c).unwrap_or(0));
+				true
+			}
+
+			Input::Keyboard(Key::Char('S')) =>
+			{
+				player.up_swing();
+				true
+			}
+
+			Input::Keyboard(Key::Char('m')) =>
+			{
+				player.toggle_mute(self.edit_state.current_edit);
+				true
+			}
+
+			Input::Keyboard(Key::Char('-')) =>
+			{
+				player.tempo.nudge(-tempo::BPM_COARSE);
+				true
+			}
+
+			Input::Keyboard(Key::Char('=')) =>
+			{
+				player.tempo.nudge(tempo::BPM_COARSE);
+				true
+			}
+
+			Input::Keyboard(Key::Char('_')) =>
+			{
+				player.tempo.nudge(-tempo::BPM_FINE);
+				true
+			}
+
+			Input::Keyboard(Key::Char('+')) =>
+			{
+				player.tempo.nudge(tempo::BPM_FINE);
+				true
+			}
+
+			Input::Keyboard(Key::Char('t')) =>
+			{
+				player.tap_tempo();
+				true
+			}
+
+			Input::Keyboard(Key::Char('o')) =>
+			{
+				player.toggle_solo(self.edit_state.current_edit);
+				true
+			}
+
+			Input::Keyboard(Key::Char('l')) =>
+			{
+				player.next_launch_quantize();
+				true
+			}
+
+			Input::Keyboard(Key::Char('L')) =>
+			{
+				player.prev_launch_quantize();
+				true
+			}
+
+			Input::Keyboard(Key::Char('e')) =>
+			{
+				player.next_stop_quantize();
+				true
+			}
+
+			Input::Keyboard(Key::Char('E')) =>
+			{
+				player.prev_stop_quantize();
+				true
+			}
+
+			_ => false
+		}
+	}
+
+	fn song_input(&mut self, player:&mut Player, evt:Input ) -> bool
+	{
+		let selected = self.song_state.selected;
+
+		match evt
+		{
+			Input::Keyboard(Key::Up) =>
+			{
+				self.song_state.selected = selected.saturating_sub(1);
+				true
+			}
+
+			Input::Keyboard(Key::Down) =>
+			{
+				self.song_state.selected = std::cmp::min(selected + 1,player.song.entries.len().saturating_sub(1));
+				true
+			}
+
+			Input::Keyboard(Key::Char(' ')) =>
+			{
+				if player.song.is_playing()
+				{
+					player.stop_song();
+				}
+				else 
+				{
+					player.start_song();
+				}
+				true
+			}
+
+			Input::Keyboard(Key::Char('\n')) =>
+			{
+				player.start_song_at(selected);
+				true
+			}
+
+			//new entries and updates take whatever is playing right now
+			Input::Keyboard(Key::Char('a')) =>
+			{
+				player.add_song_entry(selected);
+				self.song_state.selected = std::cmp::min(selected + 1,player.song.entries.len() - 1);
+				true
+			}
+
+			Input::Keyboard(Key::Char('u')) =>
+			{
+				player.update_song_entry(selected);
+				true
+			}
+
+			Input::Keyboard(Key::Char('x')) =>
+			{
+				player.song.remove(selected);
+				self.song_state.selected = std::cmp::min(selected,player.song.entries.len().saturating_sub(1));
+				true
+			}
+
+			Input::Keyboard(Key::Char('b')) =>
+			{
+				if let Some(e) = player.song.entries.get_mut(selected) { e.down_bars(); }
+				true
+			}
+
+			Input::Keyboard(Key::Char('B')) =>
+			{
+				if let Some(e) = player.song.entries.get_mut(selected) { e.up_bars(); }
+				true
+			}
+
+			Input::Keyboard(Key::Char('r')) =>
+			{
+				if let Some(e) = player.song.entries.get_mut(selected) { e.down_repeats(); }
+				true
+			}
+
+			Input::Keyboard(Key::Char('R')) =>
+			{
+				if let Some(e) = player.song.entries.get_mut(selected) { e.up_repeats(); }
+				true
+			}
+
+			Input::Keyboard(Key::Char('j')) =>
+			{
+				player.song.cycle_jump(selected,false);
+				true
+			}
+
+			Input::Keyboard(Key::Char('J')) =>
+			{
+				player.song.cycle_jump(selected,true);
+				true
+			}
+
+			//notes still launch slots so there's something to put in the entries
+			Input::Midi(_) =>
+			{
+				self.play_input(player,evt)
+			}
+
 			_ => false
 		}
 	}
@@ -266,7 +904,7 @@ impl Screen
 		{
 			Input::Quit => (true,false),
 
-			Input::Tick => (false,player.tick()),
+			Input::Tick(_) => (false,player.tick()),
 
 			Input::Keyboard(Key::F(1)) =>
 			{
@@ -278,34 +916,91 @@ impl Screen
 			{
 				eprintln!("SETTING MODE TO EDIT");
 				self.mode = Mode::Edit;
+				self.edit_state.held_keys = 0;
+				(false,true)
+			},
+			Input::Keyboard(Key::F(3)) =>
+			{
+				self.mode = Mode::Song;
+				(false,true)
+			},
+
+			//everything follows the transport of whatever is sending us clock
+			Input::Midi(MidiMessage::Start) =>
+			{
+				player.transport_start();
+				(false,true)
+			},
+
+			Input::Midi(MidiMessage::Continue) =>
+			{
+				player.transport_continue();
+				(false,true)
+			},
+
+			Input::Midi(MidiMessage::Stop) =>
+			{
+				player.transport_stop();
+				(false,true)
+			},
+
+			Input::Midi(MidiMessage::SongPosition(lsb,msb)) =>
+			{
+				player.transport_locate(((msb as usize) << 7) | lsb as usize);
+				(false,true)
+			},
+
+			Input::Midi(MidiMessage::ProgramChange(_,p)) =>
+			{
+				player.queue_pattern_all(p as usize % sequence::BANK_SIZE);
+				(false,true)
+			},
+
+			Input::Midi(MidiMessage::ControlChange(_,cc,val)) if Some(cc) == player.mute_cc =>
+			{
+				player.toggle_mute(val as usize);
 				(false,true)
 			},
+
+			Input::Midi(MidiMessage::ControlChange(_,cc,val)) if Some(cc) == player.solo_cc =>
+			{
+				player.toggle_solo(val as usize);
+				(false,true)
+			},
+
+			Input::Midi(MidiMessage::ControlChange(_,cc,val)) if Some(cc) == player.scene_cc =>
+			{
+				player.recall_scene(val as usize);
+				(false,true)
+			},
+
+			Input::Midi(MidiMessage::ControlChange(_,cc,val)) if Some(cc) == player.pattern_cc =>
+			{
+				player.midi_map[self.edit_state.current_edit].queue_pattern(val as usize % sequence::BANK_SIZE);
+				(false,true)
+			},
+
 			_=>(false,false)
 		};
 
 		let rd2 = match self.mode
 		{
 			Mode::Edit => self.edit_input(player,evt),
-			Mode::Play => self.play_input(player,evt)
+			Mode::Play => self.play_input(player,evt),
+			Mode::Song => self.song_input(player,evt)
 		};
 
 		return (rd1||rd2,quit)
 	}
 
-	fn draw_play_screen(&self,player:&Player)
+	fn draw_play_screen(&self,snap:&Snapshot)
 	{
 		write!(stdout(),"{}{}",cursor::Goto(1,3),clear::CurrentLine).unwrap();
 
-		let playing_seqs = player.midi_map.iter()
-		.enumerate()
-		.filter( |(i,s)| 
+		for seq in snap.slots.iter().flatten()
 		{
-			s.state == sequence::PlayState::Starting || s.state == sequence::PlayState::Playing
-		})
-		.take(16);
+			let i = seq.slot;
 
-		for (i,seq) in playing_seqs
-		{
 			if i == self.edit_state.current_edit
 			{
 				write!(stdout(),"{}",termion::style::Underline).unwrap();
@@ -313,21 +1008,147 @@ impl Screen
 
 			match (seq.state,seq.hold)
 			{
-				(sequence::PlayState::Playing,true) => write!(stdout(),"{}{}{}{}\n\r",clear::CurrentLine,Bg(Magenta),note_lookup::note_str(i as u8),Bg(Reset)).unwrap(),
-				(sequence::PlayState::Playing,false) => write!(stdout(),"{}{}\n\r",clear::CurrentLine,note_lookup::note_str(i as u8)).unwrap(),
-				(sequence::PlayState::Starting,_) => write!(stdout(),"{}{}{}{}\n\r",clear::CurrentLine,Bg(Cyan),note_lookup::note_str(i as u8),Bg(Reset)).unwrap(),
-				_=>()
+				(sequence::PlayState::Playing,true) => write!(stdout(),"{}{}{}{}",clear::CurrentLine,Bg(Magenta),note_lookup::note_str(i as u8),Bg(Reset)).unwrap(),
+				(sequence::PlayState::Playing,false) => write!(stdout(),"{}{}",clear::CurrentLine,note_lookup::note_str(i as u8)).unwrap(),
+				//on their way in or out
+				(state,_) =>
+				{
+					let shade = if state == sequence::PlayState::Starting { Bg(Cyan).to_string() } else { Bg(Red).to_string() };
+					write!(stdout(),"{}{}{}{}",clear::CurrentLine,shade,note_lookup::note_str(i as u8),Bg(Reset)).unwrap()
+				}
 			}
 
-			write!(stdout(),"{}",termion::style::NoUnderline).unwrap();
+			let transpose = match seq.transpose
+			{
+				Some(t) => format!(" {:+}",t),
+				None => String::new()
+			};
+
+			let pattern = match seq.queued
+			{
+				Some(q) => format!(" p{}->{}",seq.pattern + 1,q + 1),
+				None => format!(" p{}",seq.pattern + 1)
+			};
+
+			//anything kept quiet by a mute or someone else's solo is still running, just not heard
+			let silenced = if seq.silenced { " (silent)" } else { "" };
+
+			write!(stdout(),"{}{}{}{}{}\n\r",pattern,transpose,group_str(seq.choke_group,seq.mute_group,seq.muted,seq.soloed),silenced,termion::style::NoUnderline).unwrap();
 		}
 
+		self.draw_scenes(snap);
+
 		write!(stdout(),"{}",clear::AfterCursor).unwrap();
 	}
 
-	fn draw_top_panel(&self)
+	fn draw_scenes(&self,snap:&Snapshot)
 	{
-		write!(stdout(),"{}{}{}{} f1:play   f2: edit\n\r",cursor::Goto(1,1),clear::CurrentLine,Fg(Reset),Bg(Reset)).unwrap();
+		if snap.scenes.is_empty()
+		{
+			return
+		}
+
+		let mut screen = stdout();
+
+		write!(screen,"{}\n\r{}scenes:",clear::CurrentLine,clear::CurrentLine).unwrap();
+
+		for (i,name) in snap.scenes.iter().enumerate()
+		{
+			match snap.scene_queued
+			{
+				Some(q) if q == i => write!(screen,"  {}{}:{}{}",Bg(Cyan),i + 1,name,Bg(Reset)).unwrap(),
+				_ => write!(screen,"  {}:{}",i + 1,name).unwrap()
+			}
+		}
+
+		write!(screen,"\n\r").unwrap();
+	}
+
+	fn draw_song_screen(&self,snap:&Snapshot)
+	{
+		let song = &snap.song;
+		let mut screen = stdout();
+
+		write!(screen,"{}{}",cursor::Goto(1,3),clear::CurrentLine).unwrap();
+
+		match (song.state,song.entries.get(song.entry))
+		{
+			(SongState::Playing,Some(e)) => write!(screen,"playing {}    bar {}/{}    repeat {}/{}",song.entry + 1,song.bar + 1,e.bars,song.repeat + 1,e.repeats).unwrap(),
+			(SongState::Cued(_),_) => write!(screen,"cued at {}",song.entry + 1).unwrap(),
+			(_,_) => write!(screen,"stopped").unwrap()
+		}
+
+		write!(screen,"\n\r{}\n\r",clear::CurrentLine).unwrap();
+
+		if song.entries.is_empty()
+		{
+			write!(screen,"{}nothing here yet, a adds whatever is playing",clear::CurrentLine).unwrap();
+		}
+
+		//keep the selected entry on screen
+		let rows = self.h as usize - 6;
+		let first = (self.song_state.selected + 1).saturating_sub(rows);
+
+		for (i,e) in song.entries.iter().enumerate().skip(first).take(rows)
+		{
+			write!(screen,"{}",clear::CurrentLine).unwrap();
+
+			if i == self.song_state.selected
+			{
+				write!(screen,"{}",termion::style::Underline).unwrap();
+			}
+
+			if i == song.entry && song.is_playing()
+			{
+				write!(screen,"{}",Bg(Magenta)).unwrap();
+			}
+
+			write!(screen,"{:>3}  {:>2} bars x{}  ",i + 1,e.bars,e.repeats).unwrap();
+
+			for (slot,pattern) in e.slots.iter()
+			{
+				write!(screen," {}:p{}",note_lookup::note_str(*slot as u8),pattern + 1).unwrap();
+			}
+
+			if let Some(j) = e.jump
+			{
+				write!(screen,"  -> {}",j + 1).unwrap();
+			}
+
+			write!(screen,"{}{}\n\r",Bg(Reset),termion::style::NoUnderline).unwrap();
+		}
+
+		write!(screen,"{}",clear::AfterCursor).unwrap();
+	}
+
+	fn draw_top_panel(&self,snap:&Snapshot)
+	{
+		let fill = if snap.fill { "FILL" } else { "" };
+		let stopped = if snap.running { "" } else { "STOPPED " };
+
+		//following someone else shows what we think their tempo is
+		let tempo = match &snap.follow
+		{
+			Some(f) if f.locked.load(Ordering::Relaxed) => format!("ext {:.1}bpm",f.tempo.get()),
+			Some(f) if f.falling_back.load(Ordering::Relaxed) => format!("ext clock lost, {:.1}bpm",snap.tempo.get()),
+			Some(_) => "ext clock lost".to_string(),
+			None => format!("{:.1}bpm",snap.tempo.get())
+		};
+
+		let swing = match snap.swing
+		{
+			Some(s) => format!("swing:{}%",s),
+			None => String::new()
+		};
+
+		let song = match snap.song.state
+		{
+			SongState::Playing => format!("song:{}",snap.song.entry + 1),
+			SongState::Cued(_) => "song:cued".to_string(),
+			SongState::Stopped => String::new()
+		};
+
+		write!(stdout(),"{}{}{}{} {}   f1:play   f2: edit   f3: song   launch:{} stop:{}   {}   {}   {}{}{}{}\n\r",cursor::Goto(1,1),clear::CurrentLine,Fg(Reset),Bg(Reset),tempo,snap.launch_quantize,snap.stop_quantize,song,swing,Bg(Yellow),stopped,fill,Bg(Reset)).unwrap();
 		
 		for _i in 0 .. self.w
 		{
@@ -335,20 +1156,25 @@ impl Screen
 		}
 	}
 
-	pub fn draw(&self,player:&Player,)
+	pub fn draw(&self,snap:&Snapshot)
 	{
-		self.draw_top_panel();
+		self.draw_top_panel(snap);
 
 		match self.mode
 		{
 			Mode::Edit => 
 			{
-				draw_seq(stdout(),9,3,&player.midi_map[self.edit_state.current_edit])
+				draw_seq(stdout(),9,3,&snap.edit,self.edit_state.snap)
 			},
 
 			Mode::Play =>
 			{
-				self.draw_play_screen(player)
+				self.draw_play_screen(snap)
+			}
+
+			Mode::Song =>
+			{
+				self.draw_song_screen(snap)
 			}
 		}
 	}
@@ -1,5 +1,12 @@
 use crate::out_port;
-use midir::MidiOutputConnection;
+use crate::clock;
+use crate::rng::Rng;
+use crate::quantize::Quantize;
+use crate::scale;
+use crate::arp::{Arp,ArpPattern};
+use crate::midi_sink::{MidiSink,NullSink};
+
+use std::fmt;
 
 #[derive(Debug,Copy,Clone,PartialEq,Eq)]
 pub enum PlayState
@@ -7,6 +14,7 @@ pub enum PlayState
 	Off,
 	Starting,
 	Playing,
+	Stopping //plays on until its stop quantize comes round
 }
 
 #[derive(Debug,Copy,Clone)]
@@ -16,295 +24,1258 @@ pub enum Trigger
 	On (u8,u8)
 }
 
+//which passes through the sequence a step is allowed to play on
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Condition
+{
+	Always,
+	Ratio(u8,u8), //play on loop a of every b, counting from 1
+	First,
+	NotFirst,
+	Fill,
+	NotFill
+}
+
+//the order the edit keys cycle through
+const CONDITIONS : [Condition;14] =
+[
+	Condition::Always,
+	Condition::Ratio(1,2),
+	Condition::Ratio(2,2),
+	Condition::Ratio(1,3),
+	Condition::Ratio(2,3),
+	Condition::Ratio(3,3),
+	Condition::Ratio(1,4),
+	Condition::Ratio(2,4),
+	Condition::Ratio(3,4),
+	Condition::Ratio(4,4),
+	Condition::First,
+	Condition::NotFirst,
+	Condition::Fill,
+	Condition::NotFill
+];
+
+impl Condition
+{
+	pub fn parse(txt : &str) -> Option<Condition>
+	{
+		match txt
+		{
+			"" | "always" => Some(Condition::Always),
+			"first" => Some(Condition::First),
+			"!first" => Some(Condition::NotFirst),
+			"fill" => Some(Condition::Fill),
+			"!fill" => Some(Condition::NotFill),
+			_ =>
+			{
+				let mut parts = txt.split(':');
+				let a = parts.next()?.parse::<u8>().ok()?;
+				let b = parts.next()?.parse::<u8>().ok()?;
+
+				if parts.next().is_some() || a == 0 || a > b
+				{
+					None
+				}
+				else 
+				{
+					Some(Condition::Ratio(a,b))
+				}
+			}
+		}
+	}
+
+	fn passes(self, loop_count : usize, fill : bool) -> bool
+	{
+		match self
+		{
+			Condition::Always => true,
+			Condition::Ratio(a,b) => loop_count % b as usize == (a - 1) as usize,
+			Condition::First => loop_count == 0,
+			Condition::NotFirst => loop_count != 0,
+			Condition::Fill => fill,
+			Condition::NotFill => !fill
+		}
+	}
+
+	fn cycle(self, forward : bool) -> Condition
+	{
+		let idx = CONDITIONS.iter().position(|c| *c == self).unwrap_or(0);
+
+		let next = if forward
+		{
+			(idx + 1) % CONDITIONS.len()
+		}
+		else 
+		{
+			(idx + CONDITIONS.len() - 1) % CONDITIONS.len()
+		};
+
+		CONDITIONS[next]
+	}
+}
+
+impl fmt::Display for Condition
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Condition::Always => write!(f,"always"),
+			Condition::Ratio(a,b) => write!(f,"{}:{}",a,b),
+			Condition::First => write!(f,"first"),
+			Condition::NotFirst => write!(f,"!first"),
+			Condition::Fill => write!(f,"fill"),
+			Condition::NotFill => write!(f,"!fill")
+		}
+	}
+}
+
+//how the sequence picks its next step
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Direction
+{
+	Forward,
+	Reverse,
+	PingPong,
+	PingPongRepeat, //plays the end steps twice on the turn around
+	Random,
+	Brownian //wanders a step either way, leaning forward
+}
+
+const DIRECTIONS : [Direction;6] =
+[
+	Direction::Forward,
+	Direction::Reverse,
+	Direction::PingPong,
+	Direction::PingPongRepeat,
+	Direction::Random,
+	Direction::Brownian
+];
+
+impl Direction
+{
+	pub fn parse(txt : &str) -> Option<Direction>
+	{
+		DIRECTIONS.iter().find(|d| d.to_string() == txt).copied()
+	}
+
+	fn cycle(self, forward : bool) -> Direction
+	{
+		let idx = DIRECTIONS.iter().position(|d| *d == self).unwrap_or(0);
+
+		let next = if forward
+		{
+			(idx + 1) % DIRECTIONS.len()
+		}
+		else 
+		{
+			(idx + DIRECTIONS.len() - 1) % DIRECTIONS.len()
+		};
+
+		DIRECTIONS[next]
+	}
+}
+
+impl fmt::Display for Direction
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Direction::Forward => write!(f,"forward"),
+			Direction::Reverse => write!(f,"reverse"),
+			Direction::PingPong => write!(f,"pingpong"),
+			Direction::PingPongRepeat => write!(f,"pingpong_repeat"),
+			Direction::Random => write!(f,"random"),
+			Direction::Brownian => write!(f,"brownian")
+		}
+	}
+}
+
 #[derive(Debug,Copy,Clone)]
 pub struct Step
 {
-	pub trig:Trigger,
-	pub hold:bool
+	pub notes:[Trigger;STEP_VOICES], //a chord fills these from the front
+	pub hold:bool,
+	pub gate:u16, //percent of the step, GATE_FULL means "until the next step"
+	pub prob:u8, //percent chance the step plays at all
+	pub cond:Condition,
+	pub ratchet:u8, //how many times the step retriggers across its length
+	pub nudge:i16 //subticks early (negative) or late the step fires
 }
 
-const DEFAULT_STEP : Step = Step{trig:Trigger::Off,hold:false};
+pub const STEP_VOICES:usize = 8;
+
+pub const GATE_FULL:u16 = 100;
+pub const GATE_MIN:u16 = 10;
+pub const GATE_MAX:u16 = 800;
+
+pub const PROB_MAX:u8 = 100;
+const PROB_NUDGE:u8 = 10;
+
+pub const RATCHET_MAX:u8 = 8;
+
+//past this the swung step is almost on top of the next one
+pub const SWING_MAX:u8 = 75;
+pub const SWING_NUDGE:u8 = 5;
+
+const DEFAULT_STEP : Step = Step{notes:[Trigger::Off;STEP_VOICES],hold:false,gate:GATE_FULL,prob:PROB_MAX,cond:Condition::Always,ratchet:1,nudge:0};
+
+impl Step
+{
+	pub fn is_rest(&self) -> bool
+	{
+		matches!(self.notes[0],Trigger::Off)
+	}
+
+	pub fn first_note(&self) -> Option<(u8,u8)>
+	{
+		match self.notes[0]
+		{
+			Trigger::On(nn,vel) => Some((nn,vel)),
+			Trigger::Off => None
+		}
+	}
+
+	pub fn note_count(&self) -> usize
+	{
+		self.notes().count()
+	}
+
+	pub fn notes(&self) -> impl Iterator<Item=(u8,u8)> + '_
+	{
+		self.notes.iter().map_while(|t| match t
+		{
+			Trigger::On(nn,vel) => Some((*nn,*vel)),
+			Trigger::Off => None
+		})
+	}
+
+	pub fn set_note(&mut self, nn:u8, vel:u8)
+	{
+		self.clear();
+		self.notes[0] = Trigger::On(nn,vel);
+	}
+
+	//adds to the chord, a repeated note just takes the new velocity
+	pub fn add_note(&mut self, nn:u8, vel:u8) -> bool
+	{
+		for slot in self.notes.iter_mut()
+		{
+			match *slot
+			{
+				Trigger::On(n,_) if n == nn => 
+				{
+					*slot = Trigger::On(nn,vel);
+					return true
+				}
+				Trigger::Off =>
+				{
+					*slot = Trigger::On(nn,vel);
+					return true
+				}
+				_ => ()
+			}
+		}
+
+		false
+	}
+
+	//anything that can stop the step from playing every time
+	pub fn is_chancy(&self) -> bool
+	{
+		self.prob < PROB_MAX || self.cond != Condition::Always
+	}
+
+	pub fn clear(&mut self)
+	{
+		self.notes = [Trigger::Off;STEP_VOICES];
+	}
+
+	fn nudge_vel(&mut self, nudge:isize)
+	{
+		for slot in self.notes.iter_mut()
+		{
+			if let Trigger::On(nn,vel) = *slot
+			{
+				*slot = Trigger::On(nn,(vel as isize + nudge).clamp(1,0x7F) as u8);
+			}
+		}
+	}
+}
 
 //for now sequences are fixed max,
-const SEQ_MAX:usize  = 64;
+pub const SEQ_MAX:usize  = 64;
+
+//midi clock ticks per step, a step can't be shorter than one tick
+pub const TICK_RATE_MIN:usize = 1;
+pub const TICK_RATE_MAX:usize = 192;
+
+//how many patterns each slot can switch between
+pub const BANK_SIZE:usize = 16;
 
-//gonna try this
 #[derive(Copy,Clone)]
-pub struct Seq //this also contains playback and edit info
+pub struct Pattern
 {
-	pub note_on: Option<u8>,
 	pub steps: [Step;SEQ_MAX],
-	pub length: usize,
-	pub ticks_per_step:usize,
+	pub length: usize
+}
+
+const BLANK_PATTERN : Pattern = Pattern{steps:[DEFAULT_STEP;SEQ_MAX],length:0};
+
+pub const GROUP_MAX:u8 = 16;
+
+//groups count from 1, going below that takes the slot out of them
+fn up_group(group : Option<u8>) -> Option<u8>
+{
+	match group
+	{
+		Some(g) => Some(std::cmp::min(g + 1,GROUP_MAX)),
+		None => Some(1)
+	}
+}
+
+fn down_group(group : Option<u8>) -> Option<u8>
+{
+	match group
+	{
+		Some(g) if g > 1 => Some(g - 1),
+		_ => None
+	}
+}
+
+//how far one press of the velocity keys moves a step
+const VEL_NUDGE:isize = 8;
+
+//gonna try this
+#[derive(Clone)]
+pub struct Seq //this also contains playback and edit info
+{
+	pub notes_on: [Option<u8>;STEP_VOICES],
+	pub patterns: Vec<Pattern>,
+	pub pattern: usize, //the one that plays
+	pub queued: Option<usize>, //takes over at the end of the current loop
+	pub ticks_per_step:usize, //in midi clock ticks
+	pub swing:u8, //percent of a step every other step is held back
+	pub global_swing:Option<u8>, //the player's swing, when it's overriding ours
+
+	//where this slot starts and stops, nothing means go with the player's
+	pub launch_quantize:Option<Quantize>,
+	pub stop_quantize:Option<Quantize>,
+
+	//launching into a choke group stops the rest of it, in a mute group only the newest one is heard
+	pub choke_group:Option<u8>,
+	pub mute_group:Option<u8>,
+	pub launched:u64, //when it last started, for working out the newest
+	pub muted:bool,
+	pub soloed:bool,
+	pub silenced:bool, //keeps time but sends no notes, worked out by the player
+
+	//transposing, a sequence with a key range is launched by any key in it
+	//and plays shifted by how far that key is from the root
+	pub key_range:Option<(u8,u8)>,
+	pub root:u8,
+	pub transpose_key:Option<u8>,
+
+	//everything played gets pulled into key once it's been transposed
+	pub scale:Option<u16>,
+	pub scale_root:u8, //pitch class, 0 is C
+
+	pub arp:Option<Arp>,
 
 	//play info
 	tick_accum: usize,
+	note_off_in: Option<usize>, //ticks until the sounding note's gate closes
+	ratchet_hit: usize, //the next retrigger of this step, 0 when there's nothing left to do
 	pub position:usize,
+	pub loop_count:usize,
+	pub direction:Direction,
+	loop_step:usize, //steps taken since the loop began
+	step_count:usize, //steps taken since it started, swing goes on every other one of these
+	backwards:bool, //which way a ping pong is heading after position
+	next_position:usize,
+	next_loop:usize,
+	next_loop_step:usize,
+	playing_step:usize, //the step that last fired, nudges can put it ahead of position
+	fire_accum:usize, //ticks since playing_step fired
+	early_fired:bool, //the next step was nudged early and has already gone
+	late_pending:bool, //the current step is nudged late and hasn't gone yet
+	pub fill:bool,
+	pub seed:u64,
+	rng:Rng,
 	pub channel: u8,
 	pub state : PlayState,
 	pub hold : bool,
 	pub port: usize,
 
 	//edit info
-	pub edit_step : usize	
+	pub edit_step : usize,
+	pub edit_pattern : usize
 }
 
 impl Seq
 {
-	pub fn step_on(& mut self, step : usize, nn : u8)
+	pub fn step_on(& mut self, step : usize, nn : u8, vel : u8)
 	{
 		let idx = std::cmp::min(step,SEQ_MAX);
-		self.steps[idx].trig = Trigger::On(nn,127);
+		self.editing_mut().steps[idx].set_note(nn,vel);
 	}
 
 	pub fn step_off(& mut self, step : usize)
 	{
 		let idx = std::cmp::min(step,SEQ_MAX);
-		self.steps[idx].trig = Trigger::Off;
+		self.editing_mut().steps[idx].clear();
 	}
 
 	pub fn blank() -> Seq
 	{
 		Seq
 		{
-			steps: [DEFAULT_STEP;SEQ_MAX],
-			length:0,
+			patterns: vec![BLANK_PATTERN;BANK_SIZE],
+			pattern:0,
+			queued:None,
 			ticks_per_step:6,
+			swing:0,
+			global_swing:None,
+			launch_quantize:None,
+			stop_quantize:None,
+			choke_group:None,
+			mute_group:None,
+			launched:0,
+			muted:false,
+			soloed:false,
+			silenced:false,
+			key_range:None,
+			root:60,
+			transpose_key:None,
+			scale:None,
+			scale_root:0,
+			arp:None,
 			tick_accum:0,
 			position:0,
-			note_on:None,
+			loop_count:0,
+			direction:Direction::Forward,
+			loop_step:0,
+			step_count:0,
+			backwards:false,
+			next_position:0,
+			next_loop:0,
+			next_loop_step:0,
+			playing_step:0,
+			fire_accum:0,
+			early_fired:false,
+			late_pending:false,
+			fill:false,
+			seed:0,
+			rng:Rng::new(0),
+			notes_on:[None;STEP_VOICES],
+			ratchet_hit:0,
+			note_off_in:None,
 			state: PlayState::Off,
 			hold: false,
 			port: 0,
 			channel:0,
-			edit_step:0
+			edit_step:0,
+			edit_pattern:0
 		}
 	}
 
 	pub fn is_blank(&self) -> bool
 	{
-		self.length == 0
+		self.patterns.iter().all(|p| p.length == 0)
 	}
 
-	fn turn_off_playing_note(&mut self,con : &mut MidiOutputConnection)
+	pub fn playing(&self) -> &Pattern
 	{
-		match self.note_on
-		{
-			Some(nn) => 
-			{
-				out_port::note_off(con,self.channel,nn,127).unwrap();
-				self.note_on = None;
-			}
-			_ => ()
-		}
+		&self.patterns[self.pattern]
 	}
 
-	fn note_trigger(&mut self,con : &mut MidiOutputConnection) -> bool
+	pub fn editing(&self) -> &Pattern
 	{
-		match self.steps[self.position].trig
-		{
-			Trigger::On(nn,vel) => 
-			{
-				eprintln!("DOIN A NOTE ON");
-				out_port::note_on(con,self.channel,nn,vel).unwrap();
-				self.note_on = Some(nn);
-				true
-			}
-			_=> false
-		}
+		&self.patterns[self.edit_pattern]
 	}
 
-	pub fn tick(& mut  self, con : &mut MidiOutputConnection)
+	fn editing_mut(&mut self) -> &mut Pattern
 	{
-		self.tick_accum = self.tick_accum + 1;
-
-		if self.tick_accum < self.ticks_per_step { return }
-			
-		self.position = (self.position + 1) % self.length;
-		self.tick_accum = 0;
-
-		let hold = self.steps[self.position].hold;
-
-		if hold
-		{
-			if self.note_trigger(con) 
-			{ 
-				self.turn_off_playing_note(con) 
-			};
-		}
-		else 
-		{
-			self.turn_off_playing_note(con);
-			self.note_trigger(con);
-		};
+		&mut self.patterns[self.edit_pattern]
 	}
 
-	pub fn start(& mut  self, con : &mut MidiOutputConnection)
-	{		
-		self.position = 0;
-		self.tick_accum = 0;
-
-		//now check for a note on
-		match self.steps[self.position].trig
-		{
-			Trigger::On(nn,vel) => 
-			{
-				out_port::note_on(con,self.channel,nn,vel).unwrap();
-				self.note_on = Some(nn)
-			}
-			_=> ()
-		}
+	fn edit_step_mut(&mut self) -> &mut Step
+	{
+		let idx = self.edit_step;
+		&mut self.editing_mut().steps[idx]
 	}
 
-	pub fn stop(& mut  self, con : &mut MidiOutputConnection)
-	{		
-		self.position = 0;
-		self.tick_accum = 0;
+	fn turn_off_playing_note(&mut self,con : &mut dyn MidiSink)
+	{
+		self.note_off_in = None;
 
-		//first check for a note off
-		match self.note_on
+		for note in self.notes_on.iter_mut()
 		{
-			Some(nn) => 
+			if let Some(nn) = note.take()
 			{
 				out_port::note_off(con,self.channel,nn,127).unwrap();
-				self.note_on = None;
 			}
-			_ => ()
 		}
 	}
 
-	pub fn edit_step_down(&mut self)
+	//a full gate leaves the note to the next step, anything else gets its own note off
+	//ratchets take their gate from the gap between hits rather than the whole step
+	fn gate_ticks(&self,step : &Step) -> Option<usize>
 	{
-		self.edit_step = if self.edit_step == 0
+		if step.gate == GATE_FULL
 		{
-			0
+			None
 		}
 		else 
 		{
-			self.edit_step - 1
+			let window = std::cmp::max(1,self.step_ticks() / step.ratchet as usize);
+			Some(std::cmp::max(1,window * step.gate as usize / 100))
 		}
 	}
 
-	pub fn edit_step_up(&mut self)
+	//hits are spread over the step as evenly as whole ticks allow
+	fn ratchet_tick(&mut self,con : &mut dyn MidiSink)
 	{
-		self.edit_step = if self.edit_step == self.length - 1
-		{
-			self.length -1
-		}
-		else 
+		let count = self.playing().steps[self.playing_step].ratchet as usize;
+
+		if self.ratchet_hit == 0 || self.ratchet_hit >= count
 		{
-			self.edit_step + 1
+			return
 		}
-	}
 
-	pub fn edit_step_down_wrap(&mut self)
-	{
-		self.edit_step = if self.edit_step == 0
+		if self.fire_accum < self.ratchet_hit * self.step_ticks() / count
 		{
-			self.length - 1
+			return
 		}
-		else 
+
+		//more hits than ticks means some of them land together, those only play once
+		while self.ratchet_hit < count && self.ratchet_hit * self.step_ticks() / count <= self.fire_accum
 		{
-			self.edit_step - 1
+			self.ratchet_hit += 1;
 		}
+
+		self.turn_off_playing_note(con);
+		self.note_trigger(con);
 	}
 
-	pub fn edit_step_up_wrap(&mut self)
+	pub fn in_key_range(&self,nn : u8) -> bool
 	{
-		if self.length == 0
+		match self.key_range
 		{
-			return
+			Some((lo,hi)) => nn >= lo && nn <= hi,
+			None => false
 		}
+	}
 
-		self.edit_step = if self.edit_step == self.length - 1
-		{
-			0
-		}
-		else 
+	pub fn transpose(&self) -> i16
+	{
+		match (self.key_range,self.transpose_key)
 		{
-			self.edit_step + 1
+			(Some(_),Some(key)) => key as i16 - self.root as i16,
+			_ => 0
 		}
 	}
 
-	pub fn set_step_note(&mut self, nn:u8, vel:u8)
+	pub fn quantize(&self,nn : u8) -> u8
 	{
-		let mut step = & mut self.steps[self.edit_step];
-		step.trig = Trigger::On(nn,vel);
+		match self.scale
+		{
+			Some(mask) => scale::quantize(nn,self.scale_root,mask),
+			None => nn
+		}
 	}
 
-	pub fn set_step_off(&mut self)
+	//what actually gets sent for a note in the step
+	fn out_note(&self,nn : u8) -> u8
 	{
-		let mut step = & mut self.steps[self.edit_step];
-		step.trig = Trigger::Off;
+		self.quantize((nn as i16 + self.transpose()).clamp(0,0x7F) as u8)
 	}
 
-	pub fn toggle_step_hold(&mut self)
+	//arps take the pitch from the held notes and just the velocity from the step
+	fn arp_trigger(&mut self,con : &mut dyn MidiSink,step : &Step) -> bool
 	{
-		let mut step = & mut self.steps[self.edit_step];
-		step.hold = !step.hold;
-	}
+		let note = match self.arp.as_mut()
+		{
+			Some(arp) => arp.next_note(&mut self.rng),
+			None => None
+		};
 
-	pub fn drop_step(&mut self)
-	{
-		self.length = match self.length
+		match (note,step.first_note())
 		{
-			0 => 0,
-			n => n-1
+			(Some(_),_) if self.silenced => false,
+
+			(Some(nn),Some((_,vel))) =>
+			{
+				let out_nn = self.out_note(nn);
+				out_port::note_on(con,self.channel,out_nn,vel).unwrap();
+				self.notes_on[0] = Some(out_nn);
+				self.note_off_in = self.gate_ticks(step);
+				true
+			}
+			_ => false
 		}
 	}
 
-	pub fn add_step(&mut self)
+	fn note_trigger(&mut self,con : &mut dyn MidiSink) -> bool
 	{
-		self.length = match self.length
+		let step = self.playing().steps[self.playing_step];
+
+		if step.is_rest()
 		{
-			SEQ_MAX => SEQ_MAX,
-			n => n+1
+			return false
 		}
-	}
 
-	pub fn bar_up(&mut self,bar_size: usize)
-	{
-		let new_step = bar_size + self.edit_step;
+		if self.arp.is_some()
+		{
+			return self.arp_trigger(con,&step)
+		}
 
-		self.edit_step = if new_step < self.length
+		if self.silenced
 		{
-			new_step
+			return false
 		}
-		else 
+
+		for (i,(nn,vel)) in step.notes().enumerate()
 		{
-			self.edit_step
+			let out_nn = self.out_note(nn);
+			out_port::note_on(con,self.channel,out_nn,vel).unwrap();
+			self.notes_on[i] = Some(out_nn);
 		}
+
+		self.note_off_in = self.gate_ticks(&step);
+		true
 	}
 
-	pub fn bar_down(&mut self,bar_size: usize)
+	//tied steps start the new chord before letting go of the old one
+	fn legato_trigger(&mut self,con : &mut dyn MidiSink)
 	{
-		self.edit_step = if self.edit_step >= bar_size
+		let step = self.playing().steps[self.playing_step];
+
+		if step.is_rest()
 		{
-			self.edit_step - bar_size
+			return
 		}
-		else 
-		{
-			self.edit_step
-		}	
-	}
 
-	pub fn up_tick_rate(&mut self)
-	{
-		self.ticks_per_step = if self.ticks_per_step == 192
+		let old_notes = self.notes_on;
+		self.notes_on = [None;STEP_VOICES];
+
+		//a repeated pitch has to be let go first or its note off would kill the new one
+		for nn in old_notes.iter().flatten()
 		{
-			192
+			if step.notes().any(|(n,_)| self.out_note(n) == *nn)
+			{
+				out_port::note_off(con,self.channel,*nn,127).unwrap();
+			}
 		}
-		else 
+
+		self.note_trigger(con);
+
+		for nn in old_notes.iter().flatten()
 		{
-			self.ticks_per_step + 1
+			if !step.notes().any(|(n,_)| self.out_note(n) == *nn)
+			{
+				out_port::note_off(con,self.channel,*nn,127).unwrap();
+			}
 		}
 	}
 
-	pub fn down_tick_rate(&mut self)
+	//whatever is sounding when it goes quiet gets let go straight away
+	pub fn set_silenced(&mut self,silenced : bool,con : &mut dyn MidiSink)
 	{
-		self.ticks_per_step = if self.ticks_per_step == 1
+		if silenced && !self.silenced
 		{
-			1
+			self.turn_off_playing_note(con);
+		}
+
+		self.silenced = silenced;
+	}
+
+	fn gate_tick(&mut self,con : &mut dyn MidiSink)
+	{
+		match self.note_off_in
+		{
+			Some(n) if n <= 1 => self.turn_off_playing_note(con),
+			Some(n) => self.note_off_in = Some(n - 1),
+			None => ()
+		}
+	}
+
+	//conditions first so the dice only get rolled for steps that could play
+	fn step_plays(&mut self,step : &Step,loop_count : usize) -> bool
+	{
+		if step.is_rest() || !step.cond.passes(loop_count,self.fill)
+		{
+			return false
+		}
+
+		step.prob >= PROB_MAX || self.rng.chance(step.prob)
+	}
+
+	//the step length in the subticks the sequence actually counts
+	pub fn step_ticks(&self) -> usize
+	{
+		self.ticks_per_step * clock::SUBTICKS
+	}
+
+	//a nudge stays inside its own step either way
+	pub fn nudge_limit(&self) -> i16
+	{
+		std::cmp::min(self.step_ticks().saturating_sub(1),i16::MAX as usize) as i16
+	}
+
+	pub fn effective_swing(&self) -> u8
+	{
+		self.global_swing.unwrap_or(self.swing)
+	}
+
+	//swing holds back every other step in the order they play, whatever the direction,
+	//the nudge goes on top, together they stay inside the step's own window and
+	//tick keeps an early step after it from jumping ahead
+	fn step_offset(&self,idx : usize,count : usize) -> isize
+	{
+		let limit = self.step_ticks() as isize - 1;
+
+		let swing = if count % 2 == 1
+		{
+			(self.step_ticks() * self.effective_swing() as usize / 100) as isize
+		}
+		else 
+		{
+			0
+		};
+
+		(self.playing().steps[idx].nudge as isize + swing).clamp(-limit,limit)
+	}
+
+	//the step after this one is worked out ahead of time so an early nudge can fire before it's due
+	fn look_ahead(&mut self)
+	{
+		//an empty pattern counts every step as a loop so a queued one can take over
+		if self.playing().length == 0
+		{
+			self.next_position = 0;
+			self.next_loop = self.loop_count + 1;
+			self.next_loop_step = 0;
+			return
+		}
+
+		self.next_position = self.step_after(std::cmp::min(self.position,self.playing().length - 1));
+
+		//a loop is one full cycle of whatever the direction does, not just a pass back to 0
+		self.next_loop_step = self.loop_step + 1;
+		self.next_loop = self.loop_count;
+
+		if self.next_loop_step >= self.cycle_length()
+		{
+			self.next_loop_step = 0;
+			self.next_loop = self.loop_count + 1;
+		}
+	}
+
+	fn cycle_length(&self) -> usize
+	{
+		match self.direction
+		{
+			Direction::PingPong => std::cmp::max(1,2 * (self.playing().length - 1)),
+			Direction::PingPongRepeat => 2 * self.playing().length,
+			_ => self.playing().length
+		}
+	}
+
+	fn step_after(&mut self,pos : usize) -> usize
+	{
+		let length = self.playing().length;
+		let last = length - 1;
+
+		match self.direction
+		{
+			Direction::Forward => (pos + 1) % length,
+			Direction::Reverse => if pos == 0 { last } else { pos - 1 },
+
+			Direction::PingPong | Direction::PingPongRepeat if last == 0 => 0,
+
+			Direction::PingPong | Direction::PingPongRepeat =>
+			{
+				let repeat = self.direction == Direction::PingPongRepeat;
+
+				match (self.backwards,pos)
+				{
+					(false,p) if p >= last =>
+					{
+						self.backwards = true;
+						if repeat { last } else { last - 1 }
+					},
+					(true,0) =>
+					{
+						self.backwards = false;
+						if repeat { 0 } else { 1 }
+					},
+					(false,p) => p + 1,
+					(true,p) => p - 1
+				}
+			},
+
+			Direction::Random => self.rng.below(length),
+
+			Direction::Brownian => match self.rng.below(4)
+			{
+				0 => if pos == 0 { last } else { pos - 1 },
+				1 => pos,
+				_ => (pos + 1) % length
+			}
+		}
+	}
+
+	//reverse starts from the far end, everything else from the top
+	fn start_position(&self) -> usize
+	{
+		match self.direction
+		{
+			Direction::Reverse if self.playing().length > 0 => self.playing().length - 1,
+			_ => 0
+		}
+	}
+
+	fn fire_step(&mut self,con : &mut dyn MidiSink,idx : usize,loop_count : usize)
+	{
+		let step = self.playing().steps[idx];
+
+		//a tie on an arp just lets the last arp note ring on
+		if step.hold && self.arp.is_some()
+		{
+			self.playing_step = idx;
+			self.ratchet_hit = 0;
+			return
+		}
+
+		let plays = self.step_plays(&step,loop_count);
+
+		self.playing_step = idx;
+		self.fire_accum = 0;
+		self.ratchet_hit = if plays { 1 } else { 0 };
+
+		if step.hold
+		{
+			if plays
+			{
+				self.legato_trigger(con);
+			}
+		}
+		else 
+		{
+			//a long gate rings on through rests until its own note off
+			if plays || self.note_off_in.is_none()
+			{
+				self.turn_off_playing_note(con);
+			}
+
+			if plays
+			{
+				self.note_trigger(con);
+			}
+		};
+	}
+
+	pub fn tick(& mut  self, con : &mut dyn MidiSink)
+	{
+		self.gate_tick(con);
+
+		self.tick_accum = self.tick_accum + 1;
+		self.fire_accum += 1;
+
+		if self.tick_accum >= self.step_ticks() 
+		{ 
+			self.position = self.next_position;
+			self.loop_count = self.next_loop;
+			self.loop_step = self.next_loop_step;
+			self.step_count += 1;
+			self.tick_accum = 0;
+
+			if self.loop_step == 0
+			{
+				if let Some(pattern) = self.queued.take()
+				{
+					self.switch_pattern(pattern);
+				}
+			}
+
+			self.look_ahead();
+
+			//an early step has already gone, a late one waits for its nudge
+			if self.early_fired
+			{
+				self.early_fired = false;
+			}
+			else if self.step_offset(self.position,self.step_count) > 0
+			{
+				self.late_pending = true;
+			}
+			else 
+			{
+				self.fire_step(con,self.position,self.loop_count);
+			}
+		}
+		else if self.late_pending && self.tick_accum as isize >= self.step_offset(self.position,self.step_count)
+		{
+			self.late_pending = false;
+			self.fire_step(con,self.position,self.loop_count);
+		}
+		else 
+		{
+			self.ratchet_tick(con);
+		}
+
+		//the first step of a queued pattern isn't known until the switch happens
+		let switching = self.queued.is_some() && self.next_loop_step == 0;
+		let early = self.step_offset(self.next_position,self.step_count + 1);
+
+		//an early step never goes before a late or swung one ahead of it, it waits until the subtick after
+		let due = std::cmp::max(self.step_ticks() as isize + early,self.step_offset(self.position,self.step_count).max(0) + 1);
+
+		if !self.early_fired && !self.late_pending && !switching && early < 0 && self.tick_accum as isize >= due
+		{
+			self.early_fired = true;
+			self.fire_step(con,self.next_position,self.next_loop);
+		}
+	}
+
+	fn switch_pattern(&mut self,pattern : usize)
+	{
+		self.pattern = pattern;
+		self.position = self.start_position();
+		self.loop_count = 0;
+		self.loop_step = 0;
+		self.backwards = false;
+		self.early_fired = false;
+	}
+
+	//a stopped sequence switches straight away, a playing one at the end of its loop
+	pub fn queue_pattern(&mut self,pattern : usize)
+	{
+		let pattern = std::cmp::min(pattern,BANK_SIZE - 1);
+
+		if (self.state == PlayState::Playing || self.state == PlayState::Stopping) && pattern != self.pattern
+		{
+			self.queued = Some(pattern);
+		}
+		else 
+		{
+			self.pattern = pattern;
+			self.queued = None;
+		}
+	}
+
+	pub fn next_edit_pattern(&mut self)
+	{
+		self.edit_pattern = (self.edit_pattern + 1) % BANK_SIZE;
+		self.edit_step = std::cmp::min(self.edit_step,self.editing().length.saturating_sub(1));
+	}
+
+	pub fn prev_edit_pattern(&mut self)
+	{
+		self.edit_pattern = (self.edit_pattern + BANK_SIZE - 1) % BANK_SIZE;
+		self.edit_step = std::cmp::min(self.edit_step,self.editing().length.saturating_sub(1));
+	}
+
+	//back to the top without playing anything
+	fn rewind(&mut self)
+	{
+		if let Some(pattern) = self.queued.take()
+		{
+			self.pattern = pattern;
+		}
+
+		self.position = self.start_position();
+		self.tick_accum = 0;
+		self.loop_count = 0;
+		self.loop_step = 0;
+		self.step_count = 0;
+		self.backwards = false;
+		self.early_fired = false;
+		self.late_pending = false;
+		self.ratchet_hit = 0;
+		self.rng = Rng::new(self.seed);
+
+		if let Some(arp) = self.arp.as_mut()
+		{
+			arp.reset();
+		}
+
+		self.look_ahead();
+	}
+
+	//puts it where it would be had it started ticks ago, nothing sounds until its next step.
+	//it plays the whole way there into nothing so probability, random directions and arps
+	//roll the same dice they would have and end up in the same place
+	pub fn locate(& mut  self, ticks : usize, con : &mut dyn MidiSink)
+	{
+		self.turn_off_playing_note(con);
+		self.start(&mut NullSink);
+
+		for _i in 0..ticks
+		{
+			self.tick(&mut NullSink);
+		}
+
+		//none of what it played on the way actually went out
+		self.notes_on = [None;STEP_VOICES];
+		self.note_off_in = None;
+		self.ratchet_hit = 0;
+	}
+
+	//lets go of anything sounding but keeps its place, for when the transport stops
+	pub fn pause(& mut  self, con : &mut dyn MidiSink)
+	{
+		self.turn_off_playing_note(con);
+		self.ratchet_hit = 0;
+	}
+
+	pub fn start(& mut  self, con : &mut dyn MidiSink)
+	{		
+		self.rewind();
+
+		//now check for a note on, nothing can come earlier than the start
+		if self.step_offset(self.position,self.step_count) > 0
+		{
+			self.late_pending = true;
+		}
+		else 
+		{
+			self.fire_step(con,self.position,self.loop_count);
+		}
+	}
+
+	pub fn stop(& mut  self, con : &mut dyn MidiSink)
+	{		
+		self.position = 0;
+		self.tick_accum = 0;
+		self.step_count = 0;
+		self.early_fired = false;
+		self.late_pending = false;
+		self.ratchet_hit = 0;
+
+		//first check for a note off
+		self.turn_off_playing_note(con);
+	}
+
+	pub fn edit_step_down(&mut self)
+	{
+		self.edit_step = if self.edit_step == 0
+		{
+			0
+		}
+		else 
+		{
+			self.edit_step - 1
+		}
+	}
+
+	pub fn edit_step_up(&mut self)
+	{
+		self.edit_step = if self.edit_step == self.editing().length - 1
+		{
+			self.editing().length -1
+		}
+		else 
+		{
+			self.edit_step + 1
+		}
+	}
+
+	pub fn edit_step_down_wrap(&mut self)
+	{
+		self.edit_step = if self.edit_step == 0
+		{
+			self.editing().length - 1
+		}
+		else 
+		{
+			self.edit_step - 1
+		}
+	}
+
+	pub fn edit_step_up_wrap(&mut self)
+	{
+		if self.editing().length == 0
+		{
+			return
+		}
+
+		self.edit_step = if self.edit_step == self.editing().length - 1
+		{
+			0
+		}
+		else 
+		{
+			self.edit_step + 1
+		}
+	}
+
+	pub fn set_step_note(&mut self, nn:u8, vel:u8)
+	{
+		self.edit_step_mut().set_note(nn,vel);
+	}
+
+	pub fn add_step_note(&mut self, nn:u8, vel:u8)
+	{
+		self.edit_step_mut().add_note(nn,vel);
+	}
+
+	pub fn set_step_off(&mut self)
+	{
+		self.edit_step_mut().clear();
+	}
+
+	//velocity only means something on a step that has a note
+	pub fn up_step_vel(&mut self)
+	{
+		self.edit_step_mut().nudge_vel(VEL_NUDGE);
+	}
+
+	pub fn down_step_vel(&mut self)
+	{
+		self.edit_step_mut().nudge_vel(-VEL_NUDGE);
+	}
+
+	//fine steps for staccato, coarse ones once the note runs past its step
+	pub fn up_step_gate(&mut self)
+	{
+		let step = self.edit_step_mut();
+		let nudge = if step.gate < GATE_FULL { 10 } else { 50 };
+
+		step.gate = std::cmp::min(step.gate.saturating_add(nudge),GATE_MAX);
+	}
+
+	pub fn down_step_gate(&mut self)
+	{
+		let step = self.edit_step_mut();
+		let nudge = if step.gate <= GATE_FULL { 10 } else { 50 };
+
+		step.gate = std::cmp::max(step.gate.saturating_sub(nudge),GATE_MIN);
+	}
+
+	pub fn up_step_prob(&mut self)
+	{
+		let step = self.edit_step_mut();
+		step.prob = std::cmp::min(step.prob + PROB_NUDGE,PROB_MAX);
+	}
+
+	pub fn down_step_prob(&mut self)
+	{
+		let step = self.edit_step_mut();
+		step.prob = step.prob.saturating_sub(PROB_NUDGE);
+	}
+
+	pub fn next_step_cond(&mut self)
+	{
+		let step = self.edit_step_mut();
+		step.cond = step.cond.cycle(true);
+	}
+
+	pub fn prev_step_cond(&mut self)
+	{
+		let step = self.edit_step_mut();
+		step.cond = step.cond.cycle(false);
+	}
+
+	pub fn up_step_ratchet(&mut self)
+	{
+		let step = self.edit_step_mut();
+		step.ratchet = std::cmp::min(step.ratchet + 1,RATCHET_MAX);
+	}
+
+	pub fn down_step_ratchet(&mut self)
+	{
+		let step = self.edit_step_mut();
+		step.ratchet = std::cmp::max(step.ratchet - 1,1);
+	}
+
+	pub fn step_earlier(&mut self)
+	{
+		let limit = -self.nudge_limit();
+		let step = self.edit_step_mut();
+		step.nudge = std::cmp::max(step.nudge - 1,limit);
+	}
+
+	pub fn step_later(&mut self)
+	{
+		let limit = self.nudge_limit();
+		let step = self.edit_step_mut();
+		step.nudge = std::cmp::min(step.nudge + 1,limit);
+	}
+
+	pub fn toggle_step_hold(&mut self)
+	{
+		let step = self.edit_step_mut();
+		step.hold = !step.hold;
+	}
+
+	pub fn drop_step(&mut self)
+	{
+		let pattern = self.editing_mut();
+
+		pattern.length = match pattern.length
+		{
+			0 => 0,
+			n => n-1
+		}
+	}
+
+	pub fn add_step(&mut self)
+	{
+		let pattern = self.editing_mut();
+
+		pattern.length = match pattern.length
+		{
+			SEQ_MAX => SEQ_MAX,
+			n => n+1
+		}
+	}
+
+	pub fn bar_up(&mut self,bar_size: usize)
+	{
+		let new_step = bar_size + self.edit_step;
+
+		self.edit_step = if new_step < self.editing().length
+		{
+			new_step
+		}
+		else 
+		{
+			self.edit_step
+		}
+	}
+
+	pub fn bar_down(&mut self,bar_size: usize)
+	{
+		self.edit_step = if self.edit_step >= bar_size
+		{
+			self.edit_step - bar_size
+		}
+		else 
+		{
+			self.edit_step
+		}	
+	}
+
+	pub fn up_tick_rate(&mut self)
+	{
+		self.ticks_per_step = if self.ticks_per_step == TICK_RATE_MAX
+		{
+			TICK_RATE_MAX
+		}
+		else 
+		{
+			self.ticks_per_step + 1
+		}
+	}
+
+	pub fn down_tick_rate(&mut self)
+	{
+		self.ticks_per_step = if self.ticks_per_step == TICK_RATE_MIN
+		{
+			TICK_RATE_MIN
 		}
 		else 
 		{
@@ -312,6 +1283,165 @@ impl Seq
 		}
 	}
 
+	//turning it on maps the octave either side of the slot's own key
+	pub fn toggle_key_range(&mut self,root : u8)
+	{
+		self.key_range = match self.key_range
+		{
+			Some(_) => None,
+			None =>
+			{
+				self.root = root;
+				Some((root.saturating_sub(12),std::cmp::min(root as usize + 12,0x7F) as u8))
+			}
+		};
+
+		self.transpose_key = None;
+	}
+
+	pub fn widen_key_range(&mut self)
+	{
+		if let Some((lo,hi)) = self.key_range
+		{
+			self.key_range = Some((lo.saturating_sub(12),std::cmp::min(hi as usize + 12,0x7F) as u8));
+		}
+	}
+
+	//never narrower than an octave around the root
+	pub fn narrow_key_range(&mut self)
+	{
+		if let Some((lo,hi)) = self.key_range
+		{
+			let lo = std::cmp::min(lo + 12,self.root.saturating_sub(12));
+			let hi = std::cmp::max(hi.saturating_sub(12),std::cmp::min(self.root as usize + 12,0x7F) as u8);
+			self.key_range = Some((lo,hi));
+		}
+	}
+
+	//an arp needs a key range to hear its notes on, so it brings one along
+	pub fn toggle_arp(&mut self,root : u8)
+	{
+		self.arp = match self.arp
+		{
+			Some(_) => None,
+			None =>
+			{
+				if self.key_range.is_none()
+				{
+					self.toggle_key_range(root);
+				}
+
+				Some(Arp::new(ArpPattern::Up))
+			}
+		};
+
+		self.transpose_key = None;
+	}
+
+	pub fn next_arp_pattern(&mut self)
+	{
+		if let Some(arp) = self.arp.as_mut()
+		{
+			arp.next_pattern();
+		}
+	}
+
+	pub fn up_arp_octaves(&mut self)
+	{
+		if let Some(arp) = self.arp.as_mut()
+		{
+			arp.up_octaves();
+		}
+	}
+
+	pub fn down_arp_octaves(&mut self)
+	{
+		if let Some(arp) = self.arp.as_mut()
+		{
+			arp.down_octaves();
+		}
+	}
+
+	pub fn next_scale(&mut self)
+	{
+		self.scale = scale::cycle(self.scale,true);
+	}
+
+	pub fn prev_scale(&mut self)
+	{
+		self.scale = scale::cycle(self.scale,false);
+	}
+
+	pub fn up_scale_root(&mut self)
+	{
+		self.scale_root = (self.scale_root + 1) % 12;
+	}
+
+	pub fn down_scale_root(&mut self)
+	{
+		self.scale_root = (self.scale_root + 11) % 12;
+	}
+
+	pub fn next_direction(&mut self)
+	{
+		self.direction = self.direction.cycle(true);
+	}
+
+	pub fn prev_direction(&mut self)
+	{
+		self.direction = self.direction.cycle(false);
+	}
+
+	pub fn up_swing(&mut self)
+	{
+		self.swing = std::cmp::min(self.swing + SWING_NUDGE,SWING_MAX);
+	}
+
+	pub fn down_swing(&mut self)
+	{
+		self.swing = self.swing.saturating_sub(SWING_NUDGE);
+	}
+
+	pub fn next_launch_quantize(&mut self)
+	{
+		self.launch_quantize = Quantize::cycle_opt(self.launch_quantize,true);
+	}
+
+	pub fn prev_launch_quantize(&mut self)
+	{
+		self.launch_quantize = Quantize::cycle_opt(self.launch_quantize,false);
+	}
+
+	pub fn next_stop_quantize(&mut self)
+	{
+		self.stop_quantize = Quantize::cycle_opt(self.stop_quantize,true);
+	}
+
+	pub fn prev_stop_quantize(&mut self)
+	{
+		self.stop_quantize = Quantize::cycle_opt(self.stop_quantize,false);
+	}
+
+	pub fn up_choke_group(&mut self)
+	{
+		self.choke_group = up_group(self.choke_group);
+	}
+
+	pub fn down_choke_group(&mut self)
+	{
+		self.choke_group = down_group(self.choke_group);
+	}
+
+	pub fn up_mute_group(&mut self)
+	{
+		self.mute_group = up_group(self.mute_group);
+	}
+
+	pub fn down_mute_group(&mut self)
+	{
+		self.mute_group = down_group(self.mute_group);
+	}
+
 	pub fn up_channel(&mut self)
 	{
 		self.channel = if self.channel == 0x0F
@@ -359,4 +1489,368 @@ impl Seq
 			self.port - 1
 		}
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::midi_sink::RecordSink;
+
+	const ON:u8 = 0x90;
+	const OFF:u8 = 0x80;
+
+	//every message tagged with the subtick it went out on, starting counts as 0
+	fn run(seq : &mut Seq, ticks : usize) -> Vec<(usize,Vec<u8>)>
+	{
+		let sink = RecordSink::new();
+		let mut con = sink.clone();
+
+		seq.start(&mut con);
+		let mut sent : Vec<(usize,Vec<u8>)> = sink.take().into_iter().map(|m| (0,m)).collect();
+
+		for tick in 1..=ticks
+		{
+			seq.tick(&mut con);
+			sent.extend(sink.take().into_iter().map(|m| (tick,m)));
+		}
+
+		sent
+	}
+
+	//a chord per step, nothing in it is a rest
+	fn seq_with(steps : &[&[u8]]) -> Seq
+	{
+		let mut seq = Seq::blank();
+		seq.patterns[0].length = steps.len();
+
+		for (i,notes) in steps.iter().enumerate()
+		{
+			for nn in notes.iter()
+			{
+				seq.patterns[0].steps[i].add_note(*nn,100);
+			}
+		}
+
+		seq
+	}
+
+	fn note_ons(sent : &[(usize,Vec<u8>)]) -> Vec<(usize,u8)>
+	{
+		sent.iter().filter(|(_,m)| m[0] == ON).map(|(t,m)| (*t,m[1])).collect()
+	}
+
+	#[test]
+	fn gate_lets_go_partway_through_the_step()
+	{
+		let mut seq = seq_with(&[&[60],&[]]);
+		seq.patterns[0].steps[0].gate = 50;
+
+		let sent = run(&mut seq,47);
+
+		assert_eq!(sent,vec![(0,vec![ON,60,100]),(12,vec![OFF,60,127])]);
+	}
+
+	#[test]
+	fn ratchets_are_spread_over_the_step()
+	{
+		let mut seq = seq_with(&[&[60],&[]]);
+		seq.patterns[0].steps[0].ratchet = 3;
+
+		let sent = run(&mut seq,23);
+
+		assert_eq!(note_ons(&sent),vec![(0,60),(8,60),(16,60)]);
+
+		//each hit lets go of the last one first
+		assert_eq!(sent[1],(8,vec![OFF,60,127]));
+		assert_eq!(sent[3],(16,vec![OFF,60,127]));
+	}
+
+	#[test]
+	fn a_tie_keeps_shared_notes_and_lets_go_of_the_rest()
+	{
+		let mut seq = seq_with(&[&[60,64],&[64,67],&[]]);
+		seq.patterns[0].steps[1].hold = true;
+
+		let sent = run(&mut seq,48);
+
+		assert_eq!(sent,vec![
+			(0,vec![ON,60,100]),
+			(0,vec![ON,64,100]),
+			(24,vec![OFF,64,127]),
+			(24,vec![ON,64,100]),
+			(24,vec![ON,67,100]),
+			(24,vec![OFF,60,127]),
+			(48,vec![OFF,64,127]),
+			(48,vec![OFF,67,127])
+		]);
+	}
+
+	#[test]
+	fn an_early_step_waits_for_a_late_one_before_it()
+	{
+		let mut seq = seq_with(&[&[60],&[62],&[64],&[65]]);
+		seq.patterns[0].steps[1].nudge = 20;
+		seq.patterns[0].steps[2].nudge = -20;
+
+		let sent = run(&mut seq,95);
+
+		assert_eq!(note_ons(&sent),vec![(0,60),(44,62),(45,64),(72,65)]);
+	}
+
+	#[test]
+	fn an_early_step_waits_for_a_swung_one_before_it()
+	{
+		let mut seq = seq_with(&[&[60],&[62],&[64],&[65]]);
+		seq.swing = 75;
+		seq.patterns[0].steps[2].nudge = -14;
+
+		let sent = run(&mut seq,95);
+
+		assert_eq!(note_ons(&sent),vec![(0,60),(42,62),(43,64),(90,65)]);
+	}
+
+	#[test]
+	fn swing_follows_the_order_steps_play_in()
+	{
+		let mut seq = seq_with(&[&[60],&[62],&[64],&[65]]);
+		seq.swing = 75;
+		seq.direction = Direction::Reverse;
+
+		let sent = run(&mut seq,95);
+
+		assert_eq!(note_ons(&sent),vec![(0,65),(42,64),(48,62),(90,60)]);
+	}
+
+	#[test]
+	fn swing_keeps_alternating_across_an_odd_length_loop()
+	{
+		let mut seq = seq_with(&[&[60],&[62],&[64]]);
+		seq.swing = 75;
+
+		let sent = run(&mut seq,143);
+
+		assert_eq!(note_ons(&sent),vec![(0,60),(42,62),(48,64),(90,60),(96,62),(138,64)]);
+	}
+
+	#[test]
+	fn steps_go_out_pulled_into_key()
+	{
+		let mut seq = seq_with(&[&[61],&[66]]);
+		seq.scale = scale::parse("major");
+		seq.scale_root = 2;
+
+		let sent = run(&mut seq,47);
+
+		//D major has C# and F# so they're untouched
+		assert_eq!(note_ons(&sent),vec![(0,61),(24,66)]);
+
+		let mut seq = seq_with(&[&[61],&[66]]);
+		seq.scale = scale::parse("minor");
+		seq.scale_root = 2;
+
+		let sent = run(&mut seq,47);
+
+		assert_eq!(note_ons(&sent),vec![(0,60),(24,65)]);
+	}
+
+	#[test]
+	fn locating_lands_where_playing_would_have()
+	{
+		let mut played = seq_with(&[&[60],&[62],&[64]]);
+		played.direction = Direction::PingPong;
+		let mut located = played.clone();
+
+		played.start(&mut NullSink);
+
+		for _i in 0..200
+		{
+			played.tick(&mut NullSink);
+		}
+
+		located.locate(200,&mut NullSink);
+
+		assert_eq!((located.position,located.loop_count),(played.position,played.loop_count));
+	}
+
+	#[test]
+	fn locating_rolls_the_same_dice_as_playing()
+	{
+		let mut played = seq_with(&[&[60],&[62],&[64],&[65],&[67]]);
+		played.direction = Direction::Random;
+		played.seed = 7;
+
+		for step in played.patterns[0].steps.iter_mut()
+		{
+			step.prob = 50;
+		}
+
+		let mut located = played.clone();
+
+		played.start(&mut NullSink);
+
+		for _i in 0..200
+		{
+			played.tick(&mut NullSink);
+		}
+
+		located.locate(200,&mut NullSink);
+
+		//from here on both should play exactly the same notes
+		let sink = RecordSink::new();
+		let mut con = sink.clone();
+		let mut after = vec![];
+
+		for seq in [&mut played,&mut located]
+		{
+			let mut sent = vec![];
+
+			for tick in 0..400
+			{
+				seq.tick(&mut con);
+				sent.extend(sink.take().into_iter().map(|m| (tick,m)));
+			}
+
+			after.push(note_ons(&sent));
+		}
+
+		assert!(!after[0].is_empty());
+		assert_eq!(after[0],after[1]);
+	}
+
+	#[test]
+	fn conditions_parse_the_way_configs_write_them()
+	{
+		assert_eq!(Condition::parse(""),Some(Condition::Always));
+		assert_eq!(Condition::parse("!first"),Some(Condition::NotFirst));
+		assert_eq!(Condition::parse("fill"),Some(Condition::Fill));
+		assert_eq!(Condition::parse("3:4"),Some(Condition::Ratio(3,4)));
+		assert_eq!(Condition::parse("0:4"),None);
+		assert_eq!(Condition::parse("5:4"),None);
+		assert_eq!(Condition::parse("1:2:3"),None);
+		assert_eq!(Condition::parse("sometimes"),None);
+
+		for c in CONDITIONS.iter()
+		{
+			assert_eq!(Condition::parse(&c.to_string()),Some(*c));
+		}
+	}
+
+	#[test]
+	fn ratios_count_loops_from_one()
+	{
+		let plays : Vec<bool> = (0..8).map(|l| Condition::Ratio(2,4).passes(l,false)).collect();
+		assert_eq!(plays,[false,true,false,false,false,true,false,false]);
+	}
+
+	#[test]
+	fn conditioned_steps_only_play_on_their_loops()
+	{
+		let mut seq = seq_with(&[&[60],&[62]]);
+		seq.patterns[0].steps[1].cond = Condition::First;
+
+		//two steps a loop, three loops
+		let sent = run(&mut seq,6 * 24 - 1);
+		let sixty_twos = note_ons(&sent).iter().filter(|(_,nn)| *nn == 62).count();
+		assert_eq!(sixty_twos,1);
+	}
+
+	#[test]
+	fn the_same_seed_rolls_the_same_steps()
+	{
+		let mut a = seq_with(&[&[60],&[62],&[64],&[65]]);
+		a.seed = 42;
+
+		for step in a.patterns[0].steps.iter_mut()
+		{
+			step.prob = 50;
+		}
+
+		let mut b = a.clone();
+		let played = note_ons(&run(&mut a,1000));
+
+		assert!(played.len() > 5 && played.len() < 37);
+		assert_eq!(played,note_ons(&run(&mut b,1000)));
+	}
+
+	//the pitches of the first n steps played in a direction
+	fn played_order(direction : Direction, n : usize) -> Vec<u8>
+	{
+		let mut seq = seq_with(&[&[60],&[62],&[64],&[65]]);
+		seq.direction = direction;
+
+		note_ons(&run(&mut seq,(n - 1) * 24)).iter().map(|(_,nn)| *nn).collect()
+	}
+
+	#[test]
+	fn directions_play_in_their_own_order()
+	{
+		assert_eq!(played_order(Direction::Forward,5),[60,62,64,65,60]);
+		assert_eq!(played_order(Direction::Reverse,5),[65,64,62,60,65]);
+		assert_eq!(played_order(Direction::PingPong,8),[60,62,64,65,64,62,60,62]);
+		assert_eq!(played_order(Direction::PingPongRepeat,10),[60,62,64,65,65,64,62,60,60,62]);
+	}
+
+	#[test]
+	fn brownian_only_wanders_a_step_at_a_time()
+	{
+		let pitches = [60,62,64,65];
+		let order = played_order(Direction::Brownian,64);
+		assert_eq!(order.len(),64);
+
+		for pair in order.windows(2)
+		{
+			let from = pitches.iter().position(|p| *p == pair[0]).unwrap();
+			let to = pitches.iter().position(|p| *p == pair[1]).unwrap();
+			assert!([(from + 3) % 4,from,(from + 1) % 4].contains(&to),"{:?}",pair);
+		}
+	}
+
+	#[test]
+	fn random_stays_inside_the_pattern()
+	{
+		let order = played_order(Direction::Random,64);
+		assert_eq!(order.len(),64);
+		assert!(order.iter().all(|nn| [60,62,64,65].contains(nn)));
+	}
+
+	#[test]
+	fn a_queued_pattern_waits_for_the_end_of_the_loop()
+	{
+		let mut seq = seq_with(&[&[60],&[62]]);
+		seq.patterns[1].length = 1;
+		seq.patterns[1].steps[0].add_note(70,100);
+		seq.state = PlayState::Playing;
+
+		let sink = RecordSink::new();
+		let mut con = sink.clone();
+		seq.start(&mut con);
+
+		//partway into the first step
+		for _i in 0..10
+		{
+			seq.tick(&mut con);
+		}
+
+		seq.queue_pattern(1);
+		assert_eq!(seq.queued,Some(1));
+
+		for _i in 0..(4 * 24 - 10)
+		{
+			seq.tick(&mut con);
+		}
+
+		let pitches : Vec<u8> = sink.take().iter().filter(|m| m[0] == ON).map(|m| m[1]).collect();
+		assert_eq!(pitches,[60,62,70,70,70]);
+		assert_eq!((seq.pattern,seq.queued),(1,None));
+	}
+
+	#[test]
+	fn a_stopped_slot_switches_pattern_straight_away()
+	{
+		let mut seq = seq_with(&[&[60]]);
+		seq.queue_pattern(3);
+
+		assert_eq!((seq.pattern,seq.queued),(3,None));
+	}
+}
@@ -1,78 +1,870 @@
-use midir::{MidiOutputConnection};
-use crate::sequence::{PlayState,Seq};
+use crate::out_port;
+use crate::midi_sink::{MidiSink,SendError};
+use crate::sequence::{self,PlayState,Seq};
+use crate::clock;
+use crate::quantize::Quantize;
+use crate::song::{Song,SongEntry,SongEvent};
+use crate::scene::Scene;
+use crate::tempo::{Tempo,TapTempo};
+use crate::follower::FollowState;
+
+use std::sync::Arc;
 
 pub struct Player
 {
 	//global playback info (for syncing seq starts)
-	tick_accum : usize,
-	ticks_per_step : usize,
+	pub bar_tick : usize, //how far into the bar the last tick was
+	pub tick_count : usize, //the last tick counted from the top without wrapping, for launches longer than a bar
+	pub running : bool, //false while whatever we're following has us stopped
+
+	pub tempo : Arc<Tempo>, //only drives anything when we're on the internal clock
+	tap : TapTempo,
+	pub follow : Option<Arc<FollowState>>, //set when we're following an external clock
+	pub launch_quantize : Quantize,
+	pub stop_quantize : Quantize,
+	launches : u64, //counts up every time something starts
+
+	pub midi_map : [Seq;128],
+
+	pub seed : u64,
+	pub fill : bool,
+	pub swing : Option<u8>, //overrides every sequence's own swing when set
+
+	pub held : Vec<u8>, //input notes that are down right now, oldest first
+
+	pub pattern_cc : Option<u8>, //a cc that queues a pattern on the selected slot
+
+	pub song : Song,
+
+	pub scenes : Vec<Scene>,
+	pub scene_queued : Option<usize>, //waits for the top of the next bar
+	pub scene_notes : Option<(u8,u8)>, //input notes that recall scenes instead of launching slots
+	pub scene_cc : Option<u8>,
+
+	//cc values pick the slot to flip
+	pub mute_cc : Option<u8>,
+	pub solo_cc : Option<u8>,
+
+	pub out_connections: Vec<(usize,Box<dyn MidiSink>)>, //the port number each was opened on, and where it goes
+	pub clock_ports: Vec<usize>, //the out connections that get our clock and transport
+	clock_started: bool
+}
+
+impl Player
+{
+
+	pub fn blank() -> Player
+	{
+			Player
+			{
+				bar_tick : clock::BAR_TICKS - 1, //so the very first tick is the top of a bar
+				tick_count : usize::MAX, //likewise, the first tick wraps round to 0
+				running : true,
+				tempo : Arc::new(Tempo::new(120.0)),
+				tap : TapTempo::new(),
+				follow : None,
+				launch_quantize : Quantize::Sixteenth,
+				stop_quantize : Quantize::Immediate,
+				launches : 0,
+				out_connections: vec![],
+				clock_ports: vec![],
+				clock_started: false,
+				midi_map:std::array::from_fn(|_| Seq::blank()),
+				seed: 0,
+				fill: false,
+				swing: None,
+				held: vec![],
+				pattern_cc: None,
+				song: Song::new(),
+				scenes: vec![],
+				scene_queued: None,
+				scene_notes: None,
+				scene_cc: None,
+				mute_cc: None,
+				solo_cc: None
+			}
+	}
+
+	pub fn set_swing(&mut self, swing : Option<u8>)
+	{
+		self.swing = swing.map(|s| std::cmp::min(s,sequence::SWING_MAX));
+
+		for seq in self.midi_map.iter_mut()
+		{
+			seq.global_swing = self.swing;
+		}
+	}
+
+	//turning it down past nothing hands swing back to the sequences
+	pub fn up_swing(&mut self)
+	{
+		let swing = match self.swing
+		{
+			Some(s) => s + sequence::SWING_NUDGE,
+			None => 0
+		};
+
+		self.set_swing(Some(swing));
+	}
+
+	pub fn down_swing(&mut self)
+	{
+		let swing = match self.swing
+		{
+			Some(0) | None => None,
+			Some(s) => Some(s.saturating_sub(sequence::SWING_NUDGE))
+		};
+
+		self.set_swing(swing);
+	}
+
+	//every slot gets its own stream off the one seed so they don't move in lockstep
+	pub fn set_seed(&mut self, seed : u64)
+	{
+		self.seed = seed;
+
+		for (i,seq) in self.midi_map.iter_mut().enumerate()
+		{
+			seq.seed = seed.wrapping_add((i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+		}
+	}
+
+	//program changes flip the whole set over to another pattern, like changing banks
+	pub fn queue_pattern_all(&mut self, pattern : usize)
+	{
+		for seq in self.midi_map.iter_mut().filter(|s| !s.is_blank())
+		{
+			seq.queue_pattern(pattern);
+		}
+	}
+
+	pub fn tap_tempo(&mut self)
+	{
+		if let Some(bpm) = self.tap.tap()
+		{
+			self.tempo.set(bpm);
+		}
+	}
+
+	pub fn toggle_fill(&mut self)
+	{
+		self.fill = !self.fill;
+
+		for seq in self.midi_map.iter_mut()
+		{
+			seq.fill = self.fill;
+		}
+	}
+
+	fn is_running(seq : &Seq) -> bool
+	{
+		!seq.is_blank() && (seq.state == PlayState::Playing || seq.state == PlayState::Stopping)
+	}
+
+	//everything holds its place and goes quiet until a continue
+	pub fn transport_stop(&mut self)
+	{
+		self.running = false;
+
+		for seq in self.midi_map.iter_mut().filter(|s| Player::is_running(s))
+		{
+			if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+			{
+				seq.pause(port);
+			}
+		}
+
+		self.send_clock(&out_port::stop);
+	}
+
+	//picks up from wherever the last stop or song position left things, the song too
+	pub fn transport_continue(&mut self)
+	{
+		self.running = true;
+
+		if !self.song.entries.is_empty()
+		{
+			self.continue_song();
+		}
+
+		self.send_clock(&out_port::continue_);
+	}
+
+	//back to the top of the bar, whatever was running starts over on the first tick
+	pub fn transport_start(&mut self)
+	{
+		self.running = true;
+		self.bar_tick = clock::BAR_TICKS - 1;
+		self.tick_count = usize::MAX;
+		self.clock_started = false;
+
+		for seq in self.midi_map.iter_mut().filter(|s| Player::is_running(s))
+		{
+			if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+			{
+				seq.stop(port);
+			}
+
+			seq.state = PlayState::Starting;
+		}
+
+		if !self.song.entries.is_empty()
+		{
+			self.start_song();
+		}
+	}
+
+	//song position comes in sixteenths, everything running is put where it would be
+	//if it had started at the top
+	pub fn transport_locate(&mut self, spp : usize)
+	{
+		let ticks = spp * clock::SPP_TICKS;
+
+		if !self.song.entries.is_empty()
+		{
+			self.locate_song(spp);
+		}
+
+		self.send_clock(&|port| out_port::song_position(port,spp));
+
+		//the very top is just a fresh start on the next tick
+		if ticks == 0
+		{
+			self.bar_tick = clock::BAR_TICKS - 1;
+			self.tick_count = usize::MAX;
+
+			for seq in self.midi_map.iter_mut().filter(|s| Player::is_running(s))
+			{
+				if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+				{
+					seq.stop(port);
+				}
+
+				seq.state = PlayState::Starting;
+			}
+
+			return
+		}
+
+		//the next tick is the one at ticks, so everything is put on the one before it
+		self.bar_tick = (ticks - 1) % clock::BAR_TICKS;
+		self.tick_count = ticks - 1;
+
+		for seq in self.midi_map.iter_mut().filter(|s| Player::is_running(s))
+		{
+			if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+			{
+				seq.locate(ticks - 1,port);
+			}
+		}
+	}
+
+	//the slots running right now with the pattern each one is on
+	pub fn running_slots(&self) -> Vec<(usize,usize)>
+	{
+		self.midi_map.iter()
+		.enumerate()
+		.filter(|(_i,s)| !s.is_blank() && (s.state == PlayState::Starting || s.state == PlayState::Playing))
+		.map(|(i,s)| (i,s.queued.unwrap_or(s.pattern)))
+		.collect()
+	}
+
+	//slots the old entry had that the new one doesn't
+	fn leave_entry(&mut self, from : usize, to : Option<usize>)
+	{
+		let leaving : Vec<usize> = match self.song.entries.get(from)
+		{
+			Some(e) => e.slots.iter().map(|(slot,_)| *slot).collect(),
+			None => vec![]
+		};
+
+		for slot in leaving.into_iter().filter(|s| *s < 128)
+		{
+			let stays = match to.and_then(|t| self.song.entries.get(t))
+			{
+				Some(e) => e.has_slot(slot),
+				None => false
+			};
+
+			if !stays
+			{
+				self.stop_slot(slot);
+			}
+		}
+	}
+
+	//anything already on the right pattern just keeps going
+	fn enter_entry(&mut self, to : usize)
+	{
+		let slots = match self.song.entries.get(to)
+		{
+			Some(e) => e.slots.clone(),
+			None => return
+		};
+
+		for (slot,pattern) in slots.into_iter().filter(|(s,_)| *s < 128)
+		{
+			let seq = &mut self.midi_map[slot];
+			let pattern = std::cmp::min(pattern,sequence::BANK_SIZE - 1);
+
+			let running = seq.state == PlayState::Playing || seq.state == PlayState::Stopping;
+
+			if seq.is_blank()
+			{
+				continue
+			}
+
+			if running && seq.pattern == pattern && seq.queued.is_none()
+			{
+				seq.state = PlayState::Playing;
+				continue
+			}
+
+			if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+			{
+				if running
+				{
+					seq.stop(port);
+				}
+
+				seq.queued = None;
+				seq.pattern = pattern;
+				seq.start(port);
+				seq.state = PlayState::Playing;
+				self.launches += 1;
+				seq.launched = self.launches;
+			}
+		}
+	}
+
+	pub fn start_song(&mut self)
+	{
+		self.stop_song();
+		self.song.locate(0);
+	}
+
+	pub fn start_song_at(&mut self, entry : usize)
+	{
+		self.stop_song();
+		self.song.cue(entry);
+	}
+
+	pub fn stop_song(&mut self)
+	{
+		let was_playing = self.song.is_playing();
+
+		if let Some(entry) = self.song.stop()
+		{
+			self.leave_entry(entry,None);
+		}
+
+		if was_playing
+		{
+			self.send_clock(&out_port::stop);
+		}
+	}
+
+	fn send_clock(&mut self, send : &dyn Fn(&mut dyn MidiSink) -> Result<(),SendError>)
+	{
+		for idx in self.clock_ports.iter()
+		{
+			if let Some((_i,port)) = self.out_connections.get_mut(*idx)
+			{
+				send(port).unwrap();
+			}
+		}
+	}
+
+	//for shutting down, so nothing following us is left running
+	pub fn clock_stop(&mut self)
+	{
+		self.send_clock(&out_port::stop);
+	}
+
+	//followers get the transport just ahead of the clock it lands on
+	fn clock_tick(&mut self, song_event : Option<SongEvent>)
+	{
+		let fresh = !self.clock_started;
+
+		if fresh
+		{
+			self.clock_started = true;
+			self.send_clock(&out_port::start);
+		}
+
+		match song_event
+		{
+			Some(SongEvent::Enter(None,_)) =>
+			{
+				match self.song.position() - 1
+				{
+					0 if fresh => (),
+					0 => self.send_clock(&out_port::start),
+					pos =>
+					{
+						self.send_clock(&|port| out_port::song_position(port,pos / clock::SPP_TICKS));
+						self.send_clock(&out_port::continue_);
+					}
+				}
+			}
+
+			Some(SongEvent::End(_)) => self.send_clock(&out_port::stop),
+
+			_ => ()
+		}
+
+		if self.bar_tick.is_multiple_of(clock::SUBTICKS)
+		{
+			self.send_clock(&out_port::clock);
+		}
+	}
+
+	pub fn continue_song(&mut self)
+	{
+		if !self.song.is_playing()
+		{
+			self.song.resume();
+		}
+	}
+
+	//song position comes in sixteenths
+	pub fn locate_song(&mut self, spp : usize)
+	{
+		let playing = self.song.is_playing();
+		self.stop_song();
+		self.song.locate(spp * clock::SPP_TICKS);
+
+		if !playing
+		{
+			self.song.stop();
+		}
+	}
+
+	//the new entry goes in after the selected one, with whatever is playing now
+	pub fn add_song_entry(&mut self, after : usize)
+	{
+		let entry = SongEntry::new(self.running_slots());
+		let idx = if self.song.entries.is_empty() { 0 } else { after + 1 };
+		self.song.insert(idx,entry);
+	}
+
+	pub fn update_song_entry(&mut self, idx : usize)
+	{
+		let slots = self.running_slots();
+
+		if let Some(e) = self.song.entries.get_mut(idx)
+		{
+			e.slots = slots;
+		}
+	}
+
+	//keeps whatever was running, starting and playing alike
+	pub fn store_scene(&mut self, idx : usize)
+	{
+		while self.scenes.len() <= idx
+		{
+			let name = format!("scene {}",self.scenes.len() + 1);
+			self.scenes.push(Scene::new(name));
+		}
+
+		self.scenes[idx].slots = self.midi_map.iter()
+		.enumerate()
+		.filter(|(_i,s)| !s.is_blank() && (s.state == PlayState::Starting || s.state == PlayState::Playing))
+		.map(|(i,s)| (i,s.hold))
+		.collect();
+	}
+
+	pub fn recall_scene(&mut self, idx : usize)
+	{
+		if idx < self.scenes.len()
+		{
+			self.scene_queued = Some(idx);
+		}
+	}
+
+	pub fn scene_note(&self, nn : u8) -> Option<usize>
+	{
+		match self.scene_notes
+		{
+			Some((lo,hi)) if nn >= lo && nn <= hi => Some((nn - lo) as usize),
+			_ => None
+		}
+	}
+
+	fn leave_scene(&mut self, idx : usize)
+	{
+		for slot in 0..128
+		{
+			if self.midi_map[slot].state != PlayState::Off && !self.scenes[idx].has_slot(slot)
+			{
+				self.stop_slot(slot);
+			}
+		}
+	}
+
+	fn enter_scene(&mut self, idx : usize)
+	{
+		for (slot,hold) in self.scenes[idx].slots.clone().into_iter().filter(|(s,_)| *s < 128)
+		{
+			let seq = &mut self.midi_map[slot];
+			seq.hold = hold;
+
+			if seq.is_blank()
+			{
+				continue
+			}
+
+			if seq.state == PlayState::Playing || seq.state == PlayState::Stopping
+			{
+				seq.state = PlayState::Playing;
+				continue
+			}
+
+			if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+			{
+				seq.start(port);
+				seq.state = PlayState::Playing;
+				self.launches += 1;
+				seq.launched = self.launches;
+			}
+		}
+	}
+
+	pub fn tick(&mut self) -> bool
+	{
+		if !self.running
+		{
+			return false
+		}
+
+		self.bar_tick = (self.bar_tick + 1) % clock::BAR_TICKS;
+		self.tick_count = self.tick_count.wrapping_add(1);
+
+		//scenes swap over all at once on the bar
+		let scene = if self.bar_tick == 0 { self.scene_queued.take() } else { None };
+
+		if let Some(idx) = scene
+		{
+			self.leave_scene(idx);
+		}
+
+		let song_event = self.song.tick();
+
+		//outgoing parts stop before anything else happens on the bar
+		match song_event
+		{
+			Some(SongEvent::Enter(Some(from),to)) => self.leave_entry(from,Some(to)),
+			Some(SongEvent::End(from)) => self.leave_entry(from,None),
+			_ => ()
+		}
+
+		//redraws still only happen on the sixteenths
+		let hit = Quantize::Sixteenth.lands(self.bar_tick);
+
+		self.choke_launches();
+		self.update_silenced();
+
+		for i in 0..128
+		{
+			if let Some(seq) = self.midi_map.get_mut(i)
+			{
+				let launch = seq.launch_quantize.unwrap_or(self.launch_quantize).lands(self.tick_count);
+				let stop = seq.stop_quantize.unwrap_or(self.stop_quantize).lands(self.tick_count);
+
+				match (seq.is_blank(),seq.state,seq.port,launch,stop)
+				{
+					(false,PlayState::Stopping,num,_,true) =>
+					{
+						seq.state = PlayState::Off;
+
+						if let Some((_i,port)) = self.out_connections.get_mut(num)
+						{
+							seq.stop(port);
+						}
+					},
+
+					(false,PlayState::Playing,num,_,_) | (false,PlayState::Stopping,num,_,false) => 
+					{
+						if let Some((_i,port)) = self.out_connections.get_mut(num)
+						{
+							seq.tick(port);	
+						}
+					},
+
+					(false,PlayState::Starting,num,true,_) =>
+					{
+						if let Some((_i,port)) = self.out_connections.get_mut(num)
+						{
+							seq.start(port);	
+							seq.state = PlayState::Playing;
+							self.launches += 1;
+							seq.launched = self.launches;
+						}
+					},
+
+					(_,_,_,_,_) => ()
+				}
+			}
+		}
+
+		if let Some(idx) = scene
+		{
+			self.enter_scene(idx);
+		}
+
+		//incoming parts start right on the bar, and launches and bars line up with it from here on
+		if let Some(SongEvent::Enter(_,to)) = song_event
+		{
+			self.enter_entry(to);
+			self.bar_tick = 0;
+			self.tick_count = 0;
+		}
+
+		//again for anything that just started
+		self.update_silenced();
+
+		self.clock_tick(song_event);
+
+		hit || song_event.is_some() || scene.is_some()
+	}
+
+	fn launches_now(&self, seq : &Seq) -> bool
+	{
+		!seq.is_blank() && seq.state == PlayState::Starting && seq.launch_quantize.unwrap_or(self.launch_quantize).lands(self.tick_count)
+	}
 
-	pub midi_map : [Seq;128],
+	//anything about to start this tick stops the rest of its choke group first
+	fn choke_launches(&mut self)
+	{
+		let launching : Vec<(usize,u8)> = self.midi_map.iter()
+		.enumerate()
+		.filter(|(_i,s)| self.launches_now(s))
+		.filter_map(|(i,s)| s.choke_group.map(|g| (i,g)))
+		.collect();
 
-	pub out_connections: Vec<(usize,MidiOutputConnection)>,
-}
+		for (slot,group) in launching
+		{
+			for other in 0..128
+			{
+				let seq = &self.midi_map[other];
 
-impl Player
-{
+				if other != slot && seq.choke_group == Some(group) && seq.state != PlayState::Off && !self.launches_now(seq)
+				{
+					self.stop_slot(other);
+				}
+			}
+		}
+	}
 
-	pub fn blank() -> Player
+	//both take effect straight away, not on the next tick
+	pub fn toggle_mute(&mut self, slot : usize)
 	{
-			Player
+		if let Some(seq) = self.midi_map.get_mut(slot)
+		{
+			seq.muted = !seq.muted;
+			self.update_silenced();
+		}
+	}
+
+	pub fn toggle_solo(&mut self, slot : usize)
+	{
+		if let Some(seq) = self.midi_map.get_mut(slot)
+		{
+			seq.soloed = !seq.soloed;
+			self.update_silenced();
+		}
+	}
+
+	pub fn is_soloing(&self) -> bool
+	{
+		self.midi_map.iter().any(|s| s.soloed)
+	}
+
+	//muted, left out of a solo, or not the newest running member of a mute group
+	fn update_silenced(&mut self)
+	{
+		let soloing = self.is_soloing();
+		let mut newest = [0u64;sequence::GROUP_MAX as usize + 1];
+
+		for seq in self.midi_map.iter().filter(|s| s.state == PlayState::Playing || s.state == PlayState::Stopping)
+		{
+			if let Some(g) = seq.mute_group
 			{
-				tick_accum : 0,
-				ticks_per_step : 6,
-				out_connections: vec![],
-				midi_map:[Seq::blank();128]
+				newest[g as usize] = std::cmp::max(newest[g as usize],seq.launched);
+			}
+		}
+
+		for seq in self.midi_map.iter_mut()
+		{
+			let grouped_out = match seq.mute_group
+			{
+				Some(g) => seq.launched < newest[g as usize],
+				None => false
+			};
+
+			let silenced = grouped_out || seq.muted || (soloing && !seq.soloed);
+
+			if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+			{
+				seq.set_silenced(silenced,port);
 			}
+		}
 	}
 
-	pub fn tick(&mut self) -> bool
+	//a key range takes its keys away from the slots they'd normally launch
+	fn range_slot(&self, nn : u8) -> Option<usize>
+	{
+		self.midi_map.iter().position(|s| !s.is_blank() && s.in_key_range(nn))
+	}
+
+	//a slot with a stop quantize plays out to it, one that hasn't started yet just doesn't
+	fn release_slot(&mut self, slot : usize)
 	{
-		self.tick_accum = self.tick_accum + 1;
+		let seq = &mut self.midi_map[slot];
 
-		let hit = if self.tick_accum == self.ticks_per_step
+		match (seq.state,seq.stop_quantize.unwrap_or(self.stop_quantize))
 		{
-			self.tick_accum = 0;
-			true
+			(PlayState::Playing,q) if q != Quantize::Immediate => seq.state = PlayState::Stopping,
+			(PlayState::Stopping,_) => (),
+			_ => self.stop_slot(slot)
 		}
-		else 
+	}
+
+	pub fn next_launch_quantize(&mut self)
+	{
+		self.launch_quantize = self.launch_quantize.cycle(true);
+	}
+
+	pub fn prev_launch_quantize(&mut self)
+	{
+		self.launch_quantize = self.launch_quantize.cycle(false);
+	}
+
+	pub fn next_stop_quantize(&mut self)
+	{
+		self.stop_quantize = self.stop_quantize.cycle(true);
+	}
+
+	pub fn prev_stop_quantize(&mut self)
+	{
+		self.stop_quantize = self.stop_quantize.cycle(false);
+	}
+
+	fn stop_slot(&mut self, slot : usize)
+	{
+		let seq = &mut self.midi_map[slot];
+		seq.state = PlayState::Off;
+
+		if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+		{
+			seq.stop(port);
+		}
+	}
+
+	//a latched arp is stopped by hitting one of its notes again with nothing else down
+	fn arp_key_on(&mut self, slot : usize, nn : u8)
+	{
+		let seq = &mut self.midi_map[slot];
+		let latch = seq.hold;
+		let playing = seq.state != PlayState::Off;
+
+		if let Some(arp) = seq.arp.as_mut()
+		{
+			if latch && playing && arp.is_idle() && arp.notes().contains(&nn)
+			{
+				arp.clear();
+				self.release_slot(slot);
+				return
+			}
+
+			arp.press(nn,latch);
+		}
+
+		seq.state = match seq.state
 		{
-			false
+			PlayState::Off => PlayState::Starting,
+			PlayState::Stopping => PlayState::Playing,
+			state => state
 		};
+	}
 
-		for i in 0..128
+	fn arp_key_off(&mut self, slot : usize, nn : u8)
+	{
+		let seq = &mut self.midi_map[slot];
+		let latch = seq.hold;
+
+		if let Some(arp) = seq.arp.as_mut()
 		{
-			if let Some(seq) = self.midi_map.get_mut(i)
+			arp.release(nn,latch);
+
+			if !latch && arp.is_empty()
 			{
-				match (seq.is_blank(),seq.state,seq.port,hit)
-				{
-					(false,PlayState::Playing,num,_) => 
-					{
-						if let Some((_i,port)) = self.out_connections.get_mut(num)
-						{
-							seq.tick(port);	
-						}
-					},
+				self.release_slot(slot);
+			}
+		}
+	}
 
-					(false,PlayState::Starting,num,true) =>
-					{
-						if let Some((_i,port)) = self.out_connections.get_mut(num)
-						{
-							seq.start(port);	
-							seq.state = PlayState::Playing;
-						}
-					},
+	//a new key retransposes whatever is already playing rather than restarting it
+	fn key_on(&mut self, slot : usize, nn : u8)
+	{
+		if self.midi_map[slot].arp.is_some()
+		{
+			return self.arp_key_on(slot,nn)
+		}
 
-					(_,_,_,_) => ()
-				}
+		let seq = &mut self.midi_map[slot];
+
+		match seq.state
+		{
+			PlayState::Off => 
+			{
+				seq.transpose_key = Some(nn);
+				seq.state = PlayState::Starting;
 			}
+			PlayState::Stopping =>
+			{
+				seq.transpose_key = Some(nn);
+				seq.state = PlayState::Playing;
+			}
+			_ if seq.hold && seq.transpose_key == Some(nn) => self.release_slot(slot),
+			_ => seq.transpose_key = Some(nn)
+		}
+	}
+
+	//letting go falls back to the newest key still down, like a mono synth
+	fn key_off(&mut self, slot : usize, nn : u8)
+	{
+		if self.midi_map[slot].arp.is_some()
+		{
+			return self.arp_key_off(slot,nn)
+		}
+
+		let seq = &self.midi_map[slot];
+
+		if seq.hold || seq.transpose_key != Some(nn)
+		{
+			return
 		}
 
-		hit
+		match self.held.iter().rev().find(|k| seq.in_key_range(**k))
+		{
+			Some(k) => self.midi_map[slot].transpose_key = Some(*k),
+			None => self.release_slot(slot)
+		}
 	}
 
-	pub fn note_on(&mut self, nn : usize)
+	//hands back the slot the note ended up launching
+	pub fn note_on(&mut self, nn : usize) -> usize
 	{
+		self.held.retain(|k| *k as usize != nn);
+		self.held.push(nn as u8);
+
+		if let Some(slot) = self.range_slot(nn as u8)
+		{
+			self.key_on(slot,nn as u8);
+			return slot
+		}
+
 		let mut seq = &mut self.midi_map[nn];
 
 		if seq.hold
@@ -82,15 +874,11 @@ impl Player
 				PlayState::Off =>{
 					seq.state = PlayState::Starting;
 				} 
-				_ => 
-				{
-					seq.state =PlayState::Off;
-
-					if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
-					{
-						seq.stop(port);
-					}	
+				//a second press before it's finished stopping changes its mind
+				PlayState::Stopping =>{
+					seq.state = PlayState::Playing;
 				}
+				_ => self.release_slot(nn)
 				
 			}
 		}
@@ -99,14 +887,25 @@ impl Player
 			seq.state = match seq.state
 			{
 				PlayState::Off => PlayState::Starting,
+				PlayState::Stopping => PlayState::Playing,
 				_ => seq.state
 			}	
 		}
+
+		nn
 	}
 
 	pub fn note_off(&mut self, nn : usize)
 	{
-		let mut seq = &mut self.midi_map[nn];
+		self.held.retain(|k| *k as usize != nn);
+
+		if let Some(slot) = self.range_slot(nn as u8)
+		{
+			self.key_off(slot,nn as u8);
+			return
+		}
+
+		let seq = &self.midi_map[nn];
 		
 		if seq.hold
 		{
@@ -114,16 +913,377 @@ impl Player
 		}
 		else 
 		{
-			seq.state = match seq.state
-			{
-				PlayState::Off => seq.state,
-				_ => PlayState::Off
-			};
+			self.release_slot(nn);
+		}
+	}
+}
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::midi_sink::RecordSink;
+	use crate::song::SongState;
 
-			if let Some((_i,port)) = self.out_connections.get_mut(seq.port)
+	const ON:u8 = 0x90;
+	const OFF:u8 = 0x80;
+
+	//one sink on port 0, which also gets the clock
+	fn player_with(sink : &RecordSink) -> Player
+	{
+		let mut player = Player::blank();
+		player.out_connections.push((0,Box::new(sink.clone())));
+		player.clock_ports.push(0);
+		player
+	}
+
+	//the slot plays the one note on every step
+	fn fill_slot(player : &mut Player, slot : usize, nn : u8)
+	{
+		let seq = &mut player.midi_map[slot];
+		seq.patterns[0].length = 1;
+		seq.patterns[0].steps[0].add_note(nn,100);
+	}
+
+	//the ticks a note on went out on, counting the first tick as 0
+	fn note_on_ticks(player : &mut Player, sink : &RecordSink, ticks : usize) -> Vec<usize>
+	{
+		let mut at = vec![];
+
+		for tick in 0..ticks
+		{
+			player.tick();
+
+			if sink.take().iter().any(|m| m[0] & 0xF0 == ON)
 			{
-				seq.stop(port);
-			}	
+				at.push(tick);
+			}
+		}
+
+		at
+	}
+
+	#[test]
+	fn launches_longer_than_a_bar_wait_for_their_own_grid()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+
+		//two bars in midi clocks
+		player.midi_map[0].launch_quantize = Some(Quantize::Ticks(192));
+
+		note_on_ticks(&mut player,&sink,10);
+		player.note_on(0);
+
+		let at = note_on_ticks(&mut player,&sink,800);
+		assert_eq!(at.first(),Some(&(2 * clock::BAR_TICKS - 10)));
+	}
+
+	#[test]
+	fn continuing_after_a_song_position_picks_the_song_up_there()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+		fill_slot(&mut player,1,62);
+
+		for slot in 0..2
+		{
+			let mut entry = SongEntry::new(vec![(slot,0)]);
+			entry.bars = 1;
+			player.song.entries.push(entry);
+		}
+
+		player.transport_stop();
+		//a bar in, the top of the second entry
+		player.transport_locate(16);
+		player.transport_continue();
+		player.tick();
+
+		assert_eq!(player.song.state,SongState::Playing);
+		assert_eq!(player.song.entry,1);
+		assert!(sink.take().iter().any(|m| m[0] == ON && m[1] == 62));
+	}
+
+	#[test]
+	fn song_position_zero_lets_go_of_what_was_sounding()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+
+		player.note_on(0);
+		note_on_ticks(&mut player,&sink,10);
+
+		player.transport_locate(0);
+
+		assert!(sink.take().iter().any(|m| m[0] == OFF && m[1] == 60));
+		assert_eq!(player.midi_map[0].state,PlayState::Starting);
+	}
+
+	#[test]
+	fn the_song_swaps_slots_over_on_the_bar()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+		fill_slot(&mut player,1,62);
+
+		for slot in 0..2
+		{
+			let mut entry = SongEntry::new(vec![(slot,0)]);
+			entry.bars = 1;
+			player.song.entries.push(entry);
+		}
+
+		player.transport_start();
+		note_on_ticks(&mut player,&sink,clock::BAR_TICKS);
+		assert_eq!((player.midi_map[0].state,player.midi_map[1].state),(PlayState::Playing,PlayState::Off));
+
+		player.tick();
+		let sent = sink.take();
+
+		//the old slot lets go before the new one comes in
+		let off = sent.iter().position(|m| m[0] == OFF && m[1] == 60);
+		let on = sent.iter().position(|m| m[0] == ON && m[1] == 62);
+		assert!(off.is_some() && off < on);
+		assert_eq!((player.midi_map[0].state,player.midi_map[1].state),(PlayState::Off,PlayState::Playing));
+	}
+
+	#[test]
+	fn recalling_a_scene_waits_for_the_bar()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+		fill_slot(&mut player,1,62);
+		player.midi_map[0].hold = true;
+
+		player.note_on(0);
+		player.store_scene(0);
+		assert_eq!(player.scenes[0].slots,[(0,true)]);
+
+		note_on_ticks(&mut player,&sink,10);
+		player.note_on(0);
+		player.note_on(1);
+		note_on_ticks(&mut player,&sink,10);
+		player.store_scene(1);
+		assert_eq!(player.scenes[1].slots,[(1,false)]);
+
+		player.recall_scene(0);
+		note_on_ticks(&mut player,&sink,clock::BAR_TICKS - 20);
+		assert_eq!(player.scene_queued,Some(0));
+		assert_eq!((player.midi_map[0].state,player.midi_map[1].state),(PlayState::Off,PlayState::Playing));
+
+		let at = note_on_ticks(&mut player,&sink,1);
+		assert_eq!(at,[0]);
+		assert_eq!(player.scene_queued,None);
+		assert_eq!((player.midi_map[0].state,player.midi_map[1].state),(PlayState::Playing,PlayState::Off));
+		assert!(player.midi_map[0].hold);
+	}
+
+	//the pitches that went out with a note on or off since the last look
+	fn sent_pitches(sink : &RecordSink, status : u8) -> Vec<u8>
+	{
+		sink.take().iter().filter(|m| m[0] & 0xF0 == status).map(|m| m[1]).collect()
+	}
+
+	#[test]
+	fn launching_into_a_choke_group_stops_the_rest_of_it()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+		fill_slot(&mut player,1,62);
+		fill_slot(&mut player,2,64);
+		player.midi_map[0].choke_group = Some(1);
+		player.midi_map[1].choke_group = Some(1);
+
+		player.note_on(0);
+		player.note_on(2);
+		note_on_ticks(&mut player,&sink,10);
+		player.note_on(1);
+
+		//up to the next sixteenth, where it launches
+		note_on_ticks(&mut player,&sink,14);
+		sink.take();
+		player.tick();
+
+		let sent = sink.take();
+		let off = sent.iter().position(|m| m[0] == OFF && m[1] == 60);
+		let on = sent.iter().position(|m| m[0] == ON && m[1] == 62);
+		assert!(off.is_some() && off < on);
+		assert_eq!(player.midi_map[0].state,PlayState::Off);
+		assert_eq!(player.midi_map[2].state,PlayState::Playing);
+	}
+
+	#[test]
+	fn only_the_newest_of_a_mute_group_sounds()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+		fill_slot(&mut player,1,62);
+		player.midi_map[0].mute_group = Some(2);
+		player.midi_map[1].mute_group = Some(2);
+
+		player.note_on(0);
+		note_on_ticks(&mut player,&sink,10);
+		player.note_on(1);
+		note_on_ticks(&mut player,&sink,15);
+
+		//the older one keeps its place but goes quiet
+		assert_eq!(player.midi_map[0].state,PlayState::Playing);
+		assert!(player.midi_map[0].silenced);
+
+		//on to a step they both have
+		note_on_ticks(&mut player,&sink,47);
+		sink.take();
+		player.tick();
+		assert_eq!(sent_pitches(&sink,ON),[62]);
+	}
+
+	#[test]
+	fn muting_lets_go_straight_away_and_keeps_the_slot_running()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+
+		player.note_on(0);
+		note_on_ticks(&mut player,&sink,10);
+		sink.take();
+
+		player.toggle_mute(0);
+		assert_eq!(sent_pitches(&sink,OFF),[60]);
+
+		//nothing on the next step, but it's still there
+		assert!(note_on_ticks(&mut player,&sink,24).is_empty());
+		assert_eq!(player.midi_map[0].state,PlayState::Playing);
+
+		//back for the step at 48, ticks 34 on
+		player.toggle_mute(0);
+		assert_eq!(note_on_ticks(&mut player,&sink,24),[48 - 34]);
+	}
+
+	#[test]
+	fn soloing_silences_everything_else()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+		fill_slot(&mut player,1,62);
+
+		player.note_on(0);
+		player.note_on(1);
+		player.toggle_solo(1);
+		player.tick();
+		assert_eq!(sent_pitches(&sink,ON),[62]);
+
+		player.toggle_solo(1);
+		assert!(!player.is_soloing());
+		note_on_ticks(&mut player,&sink,23);
+		player.tick();
+
+		let mut both = sent_pitches(&sink,ON);
+		both.sort();
+		assert_eq!(both,[60,62]);
+	}
+
+	//just the clock and transport, in the order they went out
+	fn realtime(sink : &RecordSink) -> Vec<Vec<u8>>
+	{
+		sink.take().into_iter().filter(|m| m[0] >= 0xF0).collect()
+	}
+
+	#[test]
+	fn clock_ports_get_start_then_24_clocks_a_beat()
+	{
+		let sink = RecordSink::new();
+		let other = RecordSink::new();
+		let mut player = player_with(&sink);
+		player.out_connections.push((1,Box::new(other.clone())));
+
+		player.tick();
+		assert_eq!(realtime(&sink),[vec![0xFA],vec![0xF8]]);
+
+		for _i in 1..clock::PPQN
+		{
+			player.tick();
+		}
+
+		assert_eq!(realtime(&sink).len(),clock::MIDI_PPQN - 1);
+		assert!(realtime(&other).is_empty());
+	}
+
+	#[test]
+	fn transport_goes_out_to_followers()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+
+		player.tick();
+		realtime(&sink);
+
+		player.transport_stop();
+		//sixteenths, split into two seven bit halves
+		player.transport_locate(200);
+		player.transport_continue();
+
+		assert_eq!(realtime(&sink),[vec![0xFC],vec![0xF2,200 & 0x7F,200 >> 7],vec![0xFB]]);
+
+		//stopped means no clocks either
+		player.transport_stop();
+		realtime(&sink);
+		assert!(!player.tick());
+		assert!(realtime(&sink).is_empty());
+	}
+
+	#[test]
+	fn starting_the_song_partway_tells_followers_where()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+		fill_slot(&mut player,0,60);
+
+		for _i in 0..3
+		{
+			let mut entry = SongEntry::new(vec![(0,0)]);
+			entry.bars = 1;
+			player.song.entries.push(entry);
+		}
+
+		player.transport_start();
+		player.song.locate(2 * clock::BAR_TICKS);
+		player.tick();
+
+		//two bars in is sixteenth 32
+		assert_eq!(realtime(&sink),[vec![0xFA],vec![0xF2,32,0],vec![0xFB],vec![0xF8]]);
+	}
+
+	#[test]
+	fn song_position_puts_running_slots_where_they_would_be()
+	{
+		let sink = RecordSink::new();
+		let mut player = player_with(&sink);
+
+		let seq = &mut player.midi_map[0];
+		seq.patterns[0].length = 4;
+
+		for (i,nn) in [60,62,64,65].iter().enumerate()
+		{
+			seq.patterns[0].steps[i].add_note(*nn,100);
 		}
+
+		player.note_on(0);
+		note_on_ticks(&mut player,&sink,30);
+
+		//the sixth sixteenth is the second step on its second time round
+		player.transport_locate(5);
+		assert_eq!(sent_pitches(&sink,OFF),[62]);
+
+		player.tick();
+		assert_eq!(sent_pitches(&sink,ON),[62]);
+		assert_eq!(player.bar_tick,5 * clock::SPP_TICKS);
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,398 @@
+use crate::clock;
+
+pub const BARS_MAX:usize = 64;
+pub const REPEATS_MAX:usize = 16;
+
+//one line of the arrangement, the slots listed play their pattern for bars * repeats
+//and then it moves on to the jump, or the next line if there isn't one
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct SongEntry
+{
+	pub slots: Vec<(usize,usize)>, //slot and the pattern it plays
+	pub bars: usize,
+	pub repeats: usize,
+	pub jump: Option<usize>
+}
+
+impl SongEntry
+{
+	pub fn new(slots : Vec<(usize,usize)>) -> SongEntry
+	{
+		SongEntry{slots,bars:4,repeats:1,jump:None}
+	}
+
+	pub fn has_slot(&self, slot : usize) -> bool
+	{
+		self.slots.iter().any(|(s,_)| *s == slot)
+	}
+
+	fn span(&self) -> usize
+	{
+		self.bars * self.repeats
+	}
+
+	pub fn up_bars(&mut self)
+	{
+		self.bars = std::cmp::min(self.bars + 1,BARS_MAX);
+	}
+
+	pub fn down_bars(&mut self)
+	{
+		self.bars = std::cmp::max(self.bars - 1,1);
+	}
+
+	pub fn up_repeats(&mut self)
+	{
+		self.repeats = std::cmp::min(self.repeats + 1,REPEATS_MAX);
+	}
+
+	pub fn down_repeats(&mut self)
+	{
+		self.repeats = std::cmp::max(self.repeats - 1,1);
+	}
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum SongState
+{
+	Stopped,
+	Cued(usize), //ticks left until the bar it starts on
+	Playing
+}
+
+//what the player has to do about a tick
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum SongEvent
+{
+	Enter(Option<usize>,usize), //from the old entry (if there was one) to the new one
+	End(usize)
+}
+
+pub struct Song
+{
+	pub entries: Vec<SongEntry>,
+	pub state: SongState,
+
+	pub entry: usize,
+	pub repeat: usize,
+	pub bar: usize,
+
+	bar_ticks: usize, //into the current bar
+	ticks: usize //where the next tick lands, from the top of the song
+}
+
+impl Song
+{
+	pub fn new() -> Song
+	{
+		Song
+		{
+			entries: vec![],
+			state: SongState::Stopped,
+			entry: 0,
+			repeat: 0,
+			bar: 0,
+			bar_ticks: 0,
+			ticks: 0
+		}
+	}
+
+	//in ticks from the top, of the next tick
+	pub fn position(&self) -> usize
+	{
+		self.ticks
+	}
+
+	pub fn is_playing(&self) -> bool
+	{
+		self.state != SongState::Stopped
+	}
+
+	fn next_entry(&self, entry : usize) -> Option<usize>
+	{
+		let next = self.entries[entry].jump.unwrap_or(entry + 1);
+
+		if next < self.entries.len() { Some(next) } else { None }
+	}
+
+	//lines up on the first whole bar at or after ticks, following jumps like playback would
+	pub fn locate(&mut self, ticks : usize) -> bool
+	{
+		let mut bars = ticks.div_ceil(clock::BAR_TICKS);
+		let wait = bars * clock::BAR_TICKS - ticks;
+		let mut entry = if self.entries.is_empty() { None } else { Some(0) };
+
+		while let Some(idx) = entry
+		{
+			let e = &self.entries[idx];
+
+			if bars < e.span()
+			{
+				self.entry = idx;
+				self.repeat = bars / e.bars;
+				self.bar = bars % e.bars;
+				self.ticks = ticks;
+				self.state = SongState::Cued(wait);
+				return true
+			}
+
+			bars -= e.span();
+			entry = self.next_entry(idx);
+		}
+
+		self.state = SongState::Stopped;
+		self.ticks = 0;
+		false
+	}
+
+	//straight to the top of an entry, on the next tick
+	pub fn cue(&mut self, entry : usize) -> bool
+	{
+		if entry >= self.entries.len()
+		{
+			return false
+		}
+
+		self.entry = entry;
+		self.repeat = 0;
+		self.bar = 0;
+		self.ticks = self.entries[0..entry].iter().map(|e| e.span() * clock::BAR_TICKS).sum();
+		self.state = SongState::Cued(0);
+		true
+	}
+
+	//stopping keeps the position so it can be continued
+	pub fn stop(&mut self) -> Option<usize>
+	{
+		let was = self.state;
+		self.state = SongState::Stopped;
+
+		match was
+		{
+			SongState::Playing => Some(self.entry),
+			_ => None
+		}
+	}
+
+	pub fn resume(&mut self) -> bool
+	{
+		self.locate(self.ticks)
+	}
+
+	pub fn tick(&mut self) -> Option<SongEvent>
+	{
+		match self.state
+		{
+			SongState::Stopped => None,
+
+			SongState::Cued(wait) if wait > 0 =>
+			{
+				self.state = SongState::Cued(wait - 1);
+				self.ticks += 1;
+				None
+			}
+
+			SongState::Cued(_) =>
+			{
+				self.state = SongState::Playing;
+				self.bar_ticks = 0;
+				self.ticks += 1;
+				Some(SongEvent::Enter(None,self.entry))
+			}
+
+			SongState::Playing =>
+			{
+				self.ticks += 1;
+				self.bar_ticks += 1;
+
+				if self.bar_ticks < clock::BAR_TICKS
+				{
+					return None
+				}
+
+				self.bar_ticks = 0;
+				self.bar += 1;
+
+				//the entry might have been edited out from under us
+				let (bars,repeats) = match self.entries.get(self.entry)
+				{
+					Some(e) => (e.bars,e.repeats),
+					None => (0,0)
+				};
+
+				if self.bar < bars
+				{
+					return None
+				}
+
+				self.bar = 0;
+				self.repeat += 1;
+
+				if self.repeat < repeats
+				{
+					return None
+				}
+
+				self.repeat = 0;
+
+				let from = self.entry;
+
+				match self.entries.get(from).and_then(|_| self.next_entry(from))
+				{
+					Some(next) =>
+					{
+						self.entry = next;
+						Some(SongEvent::Enter(Some(from),next))
+					}
+
+					None =>
+					{
+						self.state = SongState::Stopped;
+						self.entry = 0;
+						self.ticks = 0;
+						Some(SongEvent::End(from))
+					}
+				}
+			}
+		}
+	}
+
+	//editing the arrangement
+	pub fn insert(&mut self, idx : usize, entry : SongEntry)
+	{
+		let idx = std::cmp::min(idx,self.entries.len());
+		self.entries.insert(idx,entry);
+
+		for e in self.entries.iter_mut()
+		{
+			match e.jump
+			{
+				Some(j) if j >= idx => e.jump = Some(j + 1),
+				_ => ()
+			}
+		}
+	}
+
+	pub fn remove(&mut self, idx : usize)
+	{
+		if idx >= self.entries.len()
+		{
+			return
+		}
+
+		self.entries.remove(idx);
+
+		if self.entry > idx
+		{
+			self.entry -= 1;
+		}
+
+		for e in self.entries.iter_mut()
+		{
+			e.jump = match e.jump
+			{
+				Some(j) if j == idx => None,
+				Some(j) if j > idx => Some(j - 1),
+				j => j
+			};
+		}
+	}
+
+	//no jump sits either side of the entries
+	pub fn cycle_jump(&mut self, idx : usize, forward : bool)
+	{
+		let len = self.entries.len();
+
+		if let Some(e) = self.entries.get_mut(idx)
+		{
+			e.jump = match (e.jump,forward)
+			{
+				(None,true) => Some(0),
+				(None,false) => len.checked_sub(1),
+				(Some(j),true) if j + 1 < len => Some(j + 1),
+				(Some(j),false) if j > 0 => Some(j - 1),
+				_ => None
+			};
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn song_of(bars_repeats : &[(usize,usize)]) -> Song
+	{
+		let mut song = Song::new();
+
+		for (slot,(bars,repeats)) in bars_repeats.iter().enumerate()
+		{
+			song.entries.push(SongEntry{slots: vec![(slot,0)],bars: *bars,repeats: *repeats,jump: None});
+		}
+
+		song
+	}
+
+	//every event with the tick it came on
+	fn events(song : &mut Song, ticks : usize) -> Vec<(usize,SongEvent)>
+	{
+		(0..ticks).filter_map(|t| song.tick().map(|e| (t,e))).collect()
+	}
+
+	#[test]
+	fn entries_play_for_bars_times_repeats_then_move_on()
+	{
+		let mut song = song_of(&[(1,2),(1,1)]);
+		song.locate(0);
+
+		assert_eq!(events(&mut song,4 * clock::BAR_TICKS),
+		[
+			(0,SongEvent::Enter(None,0)),
+			(2 * clock::BAR_TICKS,SongEvent::Enter(Some(0),1)),
+			(3 * clock::BAR_TICKS,SongEvent::End(1))
+		]);
+
+		assert!(!song.is_playing());
+	}
+
+	#[test]
+	fn jumps_are_followed_when_playing_and_locating()
+	{
+		let mut song = song_of(&[(1,1),(1,1),(1,1)]);
+		song.entries[0].jump = Some(2);
+		song.locate(0);
+
+		let played = events(&mut song,2 * clock::BAR_TICKS);
+		assert_eq!(played[1],(clock::BAR_TICKS,SongEvent::Enter(Some(0),2)));
+
+		//a bar in is the skipped to entry, and past the end is nowhere
+		assert!(song.locate(clock::BAR_TICKS));
+		assert_eq!(song.entry,2);
+		assert!(!song.locate(clock::BAR_TICKS + 1));
+	}
+
+	#[test]
+	fn locating_mid_bar_waits_for_the_next_one()
+	{
+		let mut song = song_of(&[(2,1),(1,1)]);
+
+		assert!(song.locate(100));
+		assert_eq!((song.entry,song.bar),(0,1));
+		assert_eq!(song.state,SongState::Cued(clock::BAR_TICKS - 100));
+	}
+
+	#[test]
+	fn editing_keeps_jumps_on_the_same_entries()
+	{
+		let mut song = song_of(&[(1,1),(1,1),(1,1)]);
+		song.entries[0].jump = Some(2);
+		song.entries[2].jump = Some(1);
+
+		song.insert(1,SongEntry::new(vec![]));
+		assert_eq!((song.entries[0].jump,song.entries[3].jump),(Some(3),Some(2)));
+
+		song.remove(2);
+		assert_eq!((song.entries[0].jump,song.entries[2].jump),(Some(2),None));
+	}
+}
@@ -0,0 +1,92 @@
+use crate::clock;
+
+use std::sync::atomic::{AtomicU64,Ordering};
+use std::time::{Duration,Instant};
+
+pub const BPM_MIN:f64 = 20.0;
+pub const BPM_MAX:f64 = 300.0;
+pub const BPM_FINE:f64 = 0.1;
+pub const BPM_COARSE:f64 = 1.0;
+
+//taps further apart than this start counting again
+const TAP_TIMEOUT:Duration = Duration::from_secs(2);
+const TAP_MAX:usize = 5;
+
+//shared with the timer thread, which picks up a change on its next tick
+pub struct Tempo
+{
+	bits: AtomicU64
+}
+
+impl Tempo
+{
+	pub fn new(bpm : f64) -> Tempo
+	{
+		Tempo{bits: AtomicU64::new(bpm.clamp(BPM_MIN,BPM_MAX).to_bits())}
+	}
+
+	pub fn get(&self) -> f64
+	{
+		f64::from_bits(self.bits.load(Ordering::Relaxed))
+	}
+
+	//kept to a tenth so fine steps don't drift off into long decimals
+	pub fn set(&self, bpm : f64)
+	{
+		let bpm = (bpm.clamp(BPM_MIN,BPM_MAX) * 10.0).round() / 10.0;
+		self.bits.store(bpm.to_bits(),Ordering::Relaxed);
+	}
+
+	pub fn nudge(&self, amount : f64)
+	{
+		self.set(self.get() + amount);
+	}
+}
+
+//seconds between internal ticks
+pub fn tick_secs(bpm : f64) -> f64
+{
+	60.0 / (clock::PPQN as f64 * bpm)
+}
+
+pub struct TapTempo
+{
+	taps: Vec<Instant>
+}
+
+impl TapTempo
+{
+	pub fn new() -> TapTempo
+	{
+		TapTempo{taps: vec![]}
+	}
+
+	//the tempo from the average gap between the last few taps, once there are two
+	pub fn tap(&mut self) -> Option<f64>
+	{
+		let now = Instant::now();
+
+		if let Some(last) = self.taps.last()
+		{
+			if now.duration_since(*last) > TAP_TIMEOUT
+			{
+				self.taps.clear();
+			}
+		}
+
+		self.taps.push(now);
+
+		if self.taps.len() > TAP_MAX
+		{
+			self.taps.remove(0);
+		}
+
+		if self.taps.len() < 2
+		{
+			return None
+		}
+
+		let span = now.duration_since(self.taps[0]).as_secs_f64();
+		Some(60.0 * (self.taps.len() - 1) as f64 / span)
+	}
+}